@@ -1,15 +1,23 @@
-use bevy::{prelude::*, utils::HashSet};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use bevy_rapier2d::prelude::*;
+use leafwing_input_manager::action_state::ActionState;
 
 use crate::{
-    platforms::PLATFORM_HEIGHT,
-    player::{Player, Team, Wings, WORKER_RENDER_WIDTH},
+    map::{BerryCellsLayout, Map},
+    player::{Action, Direction, Player, PlayerController, Wings, WORKER_RENDER_WIDTH},
+    rumble::{RumbleEvent, RumbleKind, RumbleTarget},
     settings::GameSettings,
-    GameState, WinCondition, WinEvent, WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_RIGHT_X,
-    WINDOW_TOP_Y, WINDOW_WIDTH,
+    teams::Team,
+    GameState, WinCondition, WinEvent,
 };
 
-const BERRY_RENDER_RADIUS: f32 = 12.0;
+pub const BERRY_RENDER_RADIUS: f32 = 12.0;
+const THROW_VELOCITY: Vec2 = Vec2::new(450.0, 350.0);
+/// How long a thrown berry flies before the thrower can catch it again
+const THROW_GRAB_DELAY: f32 = 0.3;
 
 pub struct BerriesPlugin;
 
@@ -23,6 +31,8 @@ impl Plugin for BerriesPlugin {
                 (
                     grab_berries,
                     put_berries_in_cells,
+                    throw_berries,
+                    kick_berries_into_cells,
                     check_for_berry_win,
                     handle_respawn_berries_event,
                 ),
@@ -31,14 +41,45 @@ impl Plugin for BerriesPlugin {
 }
 
 #[derive(Default, Resource)]
-pub struct BerriesCollected {
-    yellow_berries: i32,
-    purple_berries: i32,
+pub struct BerriesCollected(HashMap<Team, i32>);
+
+impl BerriesCollected {
+    pub fn get(&self, team: Team) -> i32 {
+        self.0.get(&team).copied().unwrap_or(0)
+    }
 }
 
 #[derive(Component)]
 pub struct Berry;
 
+/// The berry drawn on a worker carrying one, as opposed to the other children of a player
+#[derive(Component)]
+struct HeldBerry;
+
+/// Despawns the berry a worker carries, leaving the rest of its children such as its combat
+/// boxes in place
+pub fn drop_held_berry(player: Entity, world: &mut World) {
+    let Some(children) = world.get::<Children>(player) else {
+        return;
+    };
+    let held_berries: Vec<Entity> = children
+        .iter()
+        .copied()
+        .filter(|&child| world.get::<HeldBerry>(child).is_some())
+        .collect();
+    for held_berry in held_berries {
+        world.entity_mut(held_berry).despawn_recursive();
+    }
+}
+
+/// A berry that was thrown by a worker, who gets the credit if it lands in a cell
+#[derive(Component)]
+struct Thrown {
+    thrower: Entity,
+    player_controller: PlayerController,
+    grab_delay: Timer,
+}
+
 #[derive(Bundle)]
 pub struct BerryBundle {
     berry: Berry,
@@ -46,6 +87,7 @@ pub struct BerryBundle {
     body: RigidBody,
     collider: Collider,
     restitution: Restitution,
+    active_events: ActiveEvents,
 }
 
 impl BerryBundle {
@@ -68,6 +110,8 @@ impl BerryBundle {
             body,
             collider: Collider::ball(BERRY_RENDER_RADIUS),
             restitution: Restitution::coefficient(0.7),
+            // loose berries can fall into cells
+            active_events: ActiveEvents::COLLISION_EVENTS,
         }
     }
 }
@@ -109,23 +153,30 @@ impl BerryCellBundle {
     }
 }
 
+/// The flower a bunch of berries grows on
+#[derive(Component)]
+struct Flower;
+
 fn spawn_berry_bunch(x: f32, y: f32, commands: &mut Commands, asset_server: &Res<AssetServer>) {
     let texture = asset_server.load("flower.png");
-    commands.spawn(SpriteBundle {
-        texture,
-        sprite: Sprite {
-            custom_size: Some(Vec2::new(
-                BERRY_RENDER_RADIUS * 8.0,
-                BERRY_RENDER_RADIUS * 7.0 / 4.0,
-            )),
-            ..Default::default()
-        },
-        transform: Transform {
-            translation: Vec3::new(x, y - (11.0 / 8.0) * BERRY_RENDER_RADIUS, -2.0),
+    commands.spawn((
+        Flower,
+        SpriteBundle {
+            texture,
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(
+                    BERRY_RENDER_RADIUS * 8.0,
+                    BERRY_RENDER_RADIUS * 7.0 / 4.0,
+                )),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: Vec3::new(x, y - (11.0 / 8.0) * BERRY_RENDER_RADIUS, -2.0),
+                ..Default::default()
+            },
             ..Default::default()
         },
-        ..Default::default()
-    });
+    ));
     for i in [-1.0, 0.0, 1.0] {
         commands
             .spawn(BerryBundle::new(
@@ -163,11 +214,13 @@ fn setup(mut respawn_berries_ev: EventWriter<RespawnBerriesEvent>) {
     respawn_berries_ev.send(RespawnBerriesEvent);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_respawn_berries_event(
     respawn_berries_ev: EventReader<RespawnBerriesEvent>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     game_settings: Res<GameSettings>,
+    map: Res<Map>,
     mut berries_collected: ResMut<BerriesCollected>,
     berries: Query<
         Entity,
@@ -179,96 +232,81 @@ fn handle_respawn_berries_event(
         ),
     >,
     berry_cells: Query<Entity, With<BerryCell>>,
+    flowers: Query<Entity, With<Flower>>,
 ) {
     if respawn_berries_ev.is_empty() {
         return;
     }
-    berries_collected.yellow_berries = 0;
-    berries_collected.purple_berries = 0;
+    berries_collected.0.clear();
     for berry in &berries {
         commands.entity(berry).despawn();
     }
     for berry_cell in &berry_cells {
         commands.entity(berry_cell).despawn();
     }
+    for flower in &flowers {
+        commands.entity(flower).despawn();
+    }
 
-    for (x, y) in [
-        // layer 0
-        (
-            (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0),
-            WINDOW_BOTTOM_Y + PLATFORM_HEIGHT,
-        ),
-        (
-            -(WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0),
-            WINDOW_BOTTOM_Y + PLATFORM_HEIGHT,
-        ),
-        // layer 1
-        (0.0, WINDOW_BOTTOM_Y + WINDOW_HEIGHT / 9.0 + PLATFORM_HEIGHT),
-        // layer 2
-        (
-            0.0,
-            WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0 + PLATFORM_HEIGHT,
-        ),
-        (
-            (WINDOW_RIGHT_X - WINDOW_WIDTH / 7.0),
-            WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0 + PLATFORM_HEIGHT,
-        ),
-        (
-            -(WINDOW_RIGHT_X - WINDOW_WIDTH / 7.0),
-            WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0 + PLATFORM_HEIGHT,
-        ),
-        // layer 3
-        (
-            WINDOW_WIDTH / 10.0,
-            WINDOW_BOTTOM_Y + 3.0 * WINDOW_HEIGHT / 9.0 + PLATFORM_HEIGHT,
-        ),
-        (
-            -WINDOW_WIDTH / 10.0,
-            WINDOW_BOTTOM_Y + 3.0 * WINDOW_HEIGHT / 9.0 + PLATFORM_HEIGHT,
-        ),
-    ] {
-        spawn_berry_bunch(x, y, &mut commands, &asset_server)
+    for bunch in &map.berry_bunches {
+        spawn_berry_bunch(bunch.x, bunch.y, &mut commands, &asset_server)
     }
 
-    for team in [Team::Yellow, Team::Purple] {
-        let mut cells_placed = 0;
-        'outer: for x in -2..100 {
-            for y in (0..3).rev() {
-                let sign = match team {
-                    Team::Yellow => -1.0,
-                    Team::Purple => 1.0,
-                };
-                commands.spawn(BerryCellBundle::new(
-                    (WINDOW_WIDTH / 20.0 + x as f32 * BERRY_RENDER_RADIUS * 2.1) * sign,
-                    WINDOW_TOP_Y - (WINDOW_HEIGHT / 7.5) + y as f32 * BERRY_RENDER_RADIUS * 2.1,
-                    team,
-                    &asset_server,
-                ));
-                cells_placed += 1;
-                if cells_placed >= game_settings.berries_to_win {
-                    break 'outer;
-                }
-            }
+    for team in game_settings.teams() {
+        let layout = &map.team(team).berry_cells;
+        for cell in berry_cell_positions(team, layout, game_settings.berries_to_win) {
+            commands.spawn(BerryCellBundle::new(cell.x, cell.y, team, &asset_server));
         }
     }
 }
 
+/// Where the cells of a team go, they fill up in columns away from the middle
+pub fn berry_cell_positions(team: Team, layout: &BerryCellsLayout, count: i32) -> Vec<Vec2> {
+    let outwards = match team.side() {
+        Direction::Left => -1.0,
+        Direction::Right => 1.0,
+    };
+    let rows = layout.rows.max(1);
+    (0..count.max(0) as usize)
+        .map(|cell| {
+            let column = (cell / rows) as f32;
+            let row = (rows - 1 - cell % rows) as f32;
+            layout.position + Vec2::new(column * outwards, row) * BERRY_RENDER_RADIUS * 2.1
+        })
+        .collect()
+}
+
 fn grab_berries(
     mut collision_events: EventReader<CollisionEvent>,
-    berries: Query<Entity, (With<Berry>, Without<Player>, Without<BerryCell>)>,
+    mut berries: Query<
+        (Entity, Option<&mut Thrown>),
+        (With<Berry>, Without<Player>, Without<BerryCell>),
+    >,
     players_without_berries: Query<Entity, (With<Player>, Without<Berry>, Without<Wings>)>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    time: Res<Time>,
 ) {
+    for (_, maybe_thrown) in &mut berries {
+        if let Some(mut thrown) = maybe_thrown {
+            thrown.grab_delay.tick(time.delta());
+        }
+    }
     let mut grabbed_berries_this_frame = HashSet::new();
     for collision_event in collision_events.read() {
         if let CollisionEvent::Started(entity1, entity2, _flags) = collision_event {
             for (berry_entity, player_entity) in [(entity1, entity2), (entity2, entity1)] {
-                if let Ok(berry) = berries.get(*berry_entity) {
+                if let Ok((berry, maybe_thrown)) = berries.get(*berry_entity) {
                     if let Ok(player) = players_without_berries.get(*player_entity) {
                         if grabbed_berries_this_frame.contains(&player) {
                             continue;
                         }
+                        // the berry would be caught again as soon as it leaves the hand
+                        if maybe_thrown.is_some_and(|thrown| {
+                            thrown.thrower == player && !thrown.grab_delay.finished()
+                        }) {
+                            continue;
+                        }
                         commands.entity(berry).despawn();
                         commands
                             .entity(player)
@@ -281,6 +319,7 @@ fn grab_berries(
                                         RigidBody::Dynamic,
                                         &asset_server,
                                     ))
+                                    .insert(HeldBerry)
                                     .remove::<RigidBody>()
                                     .remove::<Collider>();
                             });
@@ -295,10 +334,11 @@ fn grab_berries(
 fn put_berries_in_cells(
     mut collision_events: EventReader<CollisionEvent>,
     mut empty_berry_cells: Query<(Entity, &Team, &mut Sprite), (With<BerryCell>, Without<Berry>)>,
-    players_with_berries: Query<(Entity, &Team), (With<Player>, With<Berry>, Without<Wings>)>,
+    players_with_berries: Query<(Entity, &Team, &Player), (With<Berry>, Without<Wings>)>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut berries_collected: ResMut<BerriesCollected>,
+    mut ev_rumble: EventWriter<RumbleEvent>,
 ) {
     let mut placed_berries_this_frame = HashSet::new();
     for collision_event in collision_events.read() {
@@ -307,28 +347,35 @@ fn put_berries_in_cells(
                 if let Ok((berry_cell, berry_cell_team, mut berry_cell_sprite)) =
                     empty_berry_cells.get_mut(*berry_cell_entity)
                 {
-                    if let Ok((player, player_team)) = players_with_berries.get(*player_entity) {
+                    if let Ok((
+                        player,
+                        player_team,
+                        &Player {
+                            player_controller, ..
+                        },
+                    )) = players_with_berries.get(*player_entity)
+                    {
                         if placed_berries_this_frame.contains(&player) {
                             continue;
                         }
                         if berry_cell_team == player_team {
-                            match player_team {
-                                Team::Yellow => berries_collected.yellow_berries += 1,
-                                Team::Purple => berries_collected.purple_berries += 1,
-                            };
                             commands
                                 .entity(player)
                                 .remove::<Berry>()
-                                .despawn_descendants();
-                            let berry_texture: Handle<Image> = asset_server.load("berry.png");
-                            berry_cell_sprite.color = Color::WHITE;
-                            berry_cell_sprite.custom_size =
-                                Some(Vec2::splat(BERRY_RENDER_RADIUS * 2.0));
-                            commands
-                                .entity(berry_cell)
-                                .insert(Berry)
-                                .insert(berry_texture);
+                                .add(drop_held_berry);
+                            fill_berry_cell(
+                                &mut commands,
+                                berry_cell,
+                                *berry_cell_team,
+                                &mut berry_cell_sprite,
+                                &mut berries_collected,
+                                &asset_server,
+                            );
                             placed_berries_this_frame.insert(player);
+                            ev_rumble.send(RumbleEvent {
+                                target: RumbleTarget::Player(player_controller),
+                                kind: RumbleKind::BerryDeposited,
+                            });
                         }
                     }
                 }
@@ -337,22 +384,181 @@ fn put_berries_in_cells(
     }
 }
 
+/// Puts a berry in an empty cell, which counts towards the economic win of the cell's team
+fn fill_berry_cell(
+    commands: &mut Commands,
+    berry_cell: Entity,
+    team: Team,
+    berry_cell_sprite: &mut Sprite,
+    berries_collected: &mut BerriesCollected,
+    asset_server: &Res<AssetServer>,
+) {
+    *berries_collected.0.entry(team).or_default() += 1;
+    let berry_texture: Handle<Image> = asset_server.load("berry.png");
+    berry_cell_sprite.color = Color::WHITE;
+    berry_cell_sprite.custom_size = Some(Vec2::splat(BERRY_RENDER_RADIUS * 2.0));
+    commands
+        .entity(berry_cell)
+        .insert(Berry)
+        .insert(berry_texture);
+}
+
+fn throw_berries(
+    players_with_berries: Query<
+        (
+            Entity,
+            &Player,
+            &ActionState<Action>,
+            &Direction,
+            &Transform,
+            &Velocity,
+        ),
+        (With<Berry>, Without<Wings>),
+    >,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, player, action_state, direction, transform, velocity) in &players_with_berries {
+        if !action_state.just_pressed(&Action::Throw) {
+            continue;
+        }
+        let facing = match direction {
+            Direction::Right => 1.0,
+            Direction::Left => -1.0,
+        };
+        commands
+            .entity(entity)
+            .remove::<Berry>()
+            .add(drop_held_berry);
+        commands.spawn((
+            BerryBundle::new(
+                transform.translation.x,
+                transform.translation.y,
+                RigidBody::Dynamic,
+                &asset_server,
+            ),
+            Velocity::linear(
+                velocity.linvel + Vec2::new(facing * THROW_VELOCITY.x, THROW_VELOCITY.y),
+            ),
+            Thrown {
+                thrower: entity,
+                player_controller: player.player_controller,
+                grab_delay: Timer::from_seconds(THROW_GRAB_DELAY, TimerMode::Once),
+            },
+        ));
+    }
+}
+
+/// Loose berries that fall into an empty cell fill it for the cell's team
+fn kick_berries_into_cells(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut empty_berry_cells: Query<(Entity, &Team, &mut Sprite), (With<BerryCell>, Without<Berry>)>,
+    loose_berries: Query<
+        (&RigidBody, Option<&Thrown>),
+        (
+            With<Berry>,
+            Without<Player>,
+            Without<BerryCell>,
+            Without<Parent>,
+        ),
+    >,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut berries_collected: ResMut<BerriesCollected>,
+    mut ev_rumble: EventWriter<RumbleEvent>,
+) {
+    let mut kicked_in_this_frame = HashSet::new();
+    for collision_event in collision_events.read() {
+        if let CollisionEvent::Started(entity1, entity2, _flags) = collision_event {
+            for (berry_cell_entity, berry_entity) in [(entity1, entity2), (entity2, entity1)] {
+                let (
+                    Ok((berry_cell, &berry_cell_team, mut berry_cell_sprite)),
+                    Ok((&RigidBody::Dynamic, maybe_thrown)),
+                ) = (
+                    empty_berry_cells.get_mut(*berry_cell_entity),
+                    loose_berries.get(*berry_entity),
+                )
+                else {
+                    continue;
+                };
+                // one berry can touch two cells, and two berries can reach one cell
+                if kicked_in_this_frame.contains(berry_entity)
+                    || kicked_in_this_frame.contains(&berry_cell)
+                {
+                    continue;
+                }
+                kicked_in_this_frame.insert(*berry_entity);
+                kicked_in_this_frame.insert(berry_cell);
+                commands.entity(*berry_entity).despawn();
+                fill_berry_cell(
+                    &mut commands,
+                    berry_cell,
+                    berry_cell_team,
+                    &mut berry_cell_sprite,
+                    &mut berries_collected,
+                    &asset_server,
+                );
+                if let Some(thrown) = maybe_thrown {
+                    ev_rumble.send(RumbleEvent {
+                        target: RumbleTarget::Player(thrown.player_controller),
+                        kind: RumbleKind::BerryDeposited,
+                    });
+                }
+            }
+        };
+    }
+}
+
 fn check_for_berry_win(
     mut ev_win: EventWriter<WinEvent>,
     berries_collected: Res<BerriesCollected>,
     game_settings: Res<GameSettings>,
 ) {
-    let win_condition = WinCondition::Economic;
-    if berries_collected.yellow_berries >= game_settings.berries_to_win {
-        ev_win.send(WinEvent {
-            team: Team::Yellow,
-            win_condition,
-        });
+    for team in game_settings.teams() {
+        if berries_collected.get(team) >= game_settings.berries_to_win {
+            ev_win.send(WinEvent {
+                team,
+                win_condition: WinCondition::Economic,
+            });
+        }
     }
-    if berries_collected.purple_berries >= game_settings.berries_to_win {
-        ev_win.send(WinEvent {
-            team: Team::Purple,
-            win_condition,
-        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+    use crate::combat::CombatBox;
+
+    #[test]
+    fn dropping_the_held_berry_keeps_the_combat_boxes() {
+        let mut world = World::new();
+        let player = world
+            .spawn(Berry)
+            .with_children(|parent| {
+                for combat_box in CombatBox::ALL {
+                    parent.spawn(combat_box);
+                }
+                parent.spawn(HeldBerry);
+            })
+            .id();
+
+        // depositing, throwing and becoming a speed worker all let go of the berry like this
+        let mut queue = CommandQueue::default();
+        Commands::new(&mut queue, &world)
+            .entity(player)
+            .remove::<Berry>()
+            .add(drop_held_berry);
+        queue.apply(&mut world);
+
+        assert!(world.get::<Berry>(player).is_none());
+        let children = world.get::<Children>(player).unwrap();
+        let combat_boxes: Vec<CombatBox> = children
+            .iter()
+            .filter_map(|&child| world.get::<CombatBox>(child).copied())
+            .collect();
+        assert_eq!(combat_boxes, CombatBox::ALL);
+        assert_eq!(world.query::<&HeldBerry>().iter(&world).count(), 0);
     }
 }
@@ -0,0 +1,475 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use leafwing_input_manager::{plugin::InputManagerSystem, prelude::*};
+
+use crate::{
+    berries::{Berry, BerryCell},
+    gates::{Gate, GateKind},
+    join::{release_join_gate, remove_player, JoinGate},
+    player::{
+        add_delayed_player_spawners, press_with_value, Action, DelayedPlayerSpawner, Player,
+        PlayerController, Queen, SpawnPlayerEvent, Speed, Wings,
+    },
+    queue::Waiting,
+    reconnect::OrphanedPlayers,
+    settings::GameSettings,
+    ship::{RidingOnShip, Ship},
+    teams::Team,
+    weapons::Weapon,
+    GameState,
+};
+
+const BOT_ARRIVE_DISTANCE: f32 = 10.0;
+const BOT_JUMP_HEIGHT: f32 = 40.0;
+const BOT_STUCK_SPEED: f32 = 10.0;
+const BOT_STUCK_TIME: f32 = 0.3;
+const BOT_FLAP_INTERVAL: f32 = 0.2;
+const BOT_ATTACK_HEIGHT: f32 = 60.0;
+const BOT_DIVE_RANGE: f32 = 30.0;
+const BOT_DANGER_RADIUS: f32 = 150.0;
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BotIds>()
+            .add_systems(
+                PreUpdate,
+                (add_bot_brains, drive_bots)
+                    .chain()
+                    .after(InputManagerSystem::Update),
+            )
+            .add_systems(
+                Update,
+                fill_teams_with_bots
+                    .before(add_delayed_player_spawners)
+                    .run_if(in_state(GameState::Join)),
+            );
+    }
+}
+
+/// Hands out a unique controller to every bot that joins
+#[derive(Resource, Default)]
+pub struct BotIds(u32);
+
+impl BotIds {
+    pub fn next(&mut self) -> PlayerController {
+        self.0 += 1;
+        PlayerController::Bot { id: self.0 }
+    }
+}
+
+#[derive(Component)]
+struct BotBrain {
+    flap_timer: Timer,
+    stuck_timer: Timer,
+}
+
+impl Default for BotBrain {
+    fn default() -> Self {
+        Self {
+            flap_timer: Timer::from_seconds(BOT_FLAP_INTERVAL, TimerMode::Once),
+            stuck_timer: Timer::from_seconds(BOT_STUCK_TIME, TimerMode::Once),
+        }
+    }
+}
+
+fn add_bot_brains(
+    players: Query<(Entity, &PlayerController), Without<BotBrain>>,
+    mut commands: Commands,
+) {
+    for (entity, player_controller) in &players {
+        if let PlayerController::Bot { .. } = player_controller {
+            commands.entity(entity).insert(BotBrain::default());
+        }
+    }
+}
+
+/// Lets a bot take over the slot of a player who left in the middle of a game
+pub fn hand_over_to_bot(
+    commands: &mut Commands,
+    player_entity: Entity,
+    player: &Player,
+    bot_ids: &mut BotIds,
+) -> PlayerController {
+    let player_controller = bot_ids.next();
+    commands.entity(player_entity).insert((
+        Player {
+            player_controller,
+            is_on_ground: player.is_on_ground,
+        },
+        player_controller,
+        InputMap::<Action>::default(),
+        ActionState::<Action>::default(),
+    ));
+    player_controller
+}
+
+/// Gives the slot of a bot to a player, keeping its team, role and berry
+pub fn hand_over_to_player(
+    commands: &mut Commands,
+    bot_entity: Entity,
+    bot: &Player,
+    player_controller: PlayerController,
+    input_map: InputMap<Action>,
+) {
+    commands
+        .entity(bot_entity)
+        .insert((
+            Player {
+                player_controller,
+                is_on_ground: bot.is_on_ground,
+            },
+            player_controller,
+            input_map,
+            ActionState::<Action>::default(),
+        ))
+        .remove::<BotBrain>();
+}
+
+/// A player on a team, or one that is waiting to spawn
+struct TeamSlot {
+    entity: Entity,
+    is_bot: bool,
+    is_queen: bool,
+    is_spawned: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fill_teams_with_bots(
+    mut commands: Commands,
+    players: Query<(
+        Entity,
+        &Player,
+        &Team,
+        Has<Queen>,
+        Has<Berry>,
+        &Transform,
+        Option<&RidingOnShip>,
+    )>,
+    delayed_player_spawners: Query<(Entity, &DelayedPlayerSpawner), Without<Waiting>>,
+    mut join_gates: Query<(Entity, &Team, &mut TextureAtlas), With<JoinGate>>,
+    game_settings: Res<GameSettings>,
+    mut bot_ids: ResMut<BotIds>,
+    mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
+    asset_server: Res<AssetServer>,
+    orphaned_players: Res<OrphanedPlayers>,
+) {
+    if !game_settings.fill_with_bots {
+        return;
+    }
+    let slots: Vec<(Team, TeamSlot)> = players
+        .iter()
+        .map(|(entity, player, &team, is_queen, ..)| {
+            (
+                team,
+                TeamSlot {
+                    entity,
+                    is_bot: orphaned_players.is_filler_bot(player.player_controller),
+                    is_queen,
+                    is_spawned: true,
+                },
+            )
+        })
+        .chain(delayed_player_spawners.iter().map(|(entity, spawner)| {
+            (
+                spawner.event.team,
+                TeamSlot {
+                    entity,
+                    is_bot: orphaned_players.is_filler_bot(spawner.event.player_controller),
+                    is_queen: spawner.event.is_queen,
+                    is_spawned: false,
+                },
+            )
+        }))
+        .collect();
+    // bots only fill in around humans, they do not play on their own
+    let team_size = if slots.iter().any(|(_, slot)| !slot.is_bot) {
+        game_settings.team_size
+    } else {
+        0
+    };
+
+    for team in game_settings.teams() {
+        let team_slots: Vec<&TeamSlot> = slots
+            .iter()
+            .filter(|(slot_team, _)| *slot_team == team)
+            .map(|(_, slot)| slot)
+            .collect();
+        let has_human_queen = team_slots.iter().any(|slot| !slot.is_bot && slot.is_queen);
+        let bot_queen = team_slots.iter().find(|slot| slot.is_bot && slot.is_queen);
+        let bot_worker = team_slots.iter().find(|slot| slot.is_bot && !slot.is_queen);
+
+        let mut spawn_bot = |is_queen| {
+            ev_spawn_players.send(SpawnPlayerEvent {
+                team,
+                is_queen,
+                player_controller: bot_ids.next(),
+                delay: 0.0,
+                start_invincible: false,
+            });
+        };
+        let bot_to_remove = if has_human_queen && bot_queen.is_some() {
+            // a human took over as queen
+            bot_queen
+        } else if !has_human_queen && bot_queen.is_none() && team_size > 0 {
+            // a bot takes the place of a missing queen
+            if team_slots.len() >= team_size {
+                if bot_worker.is_none() {
+                    continue;
+                }
+                spawn_bot(true);
+                bot_worker
+            } else {
+                spawn_bot(true);
+                None
+            }
+        } else if team_slots.len() > team_size {
+            bot_worker.or(bot_queen)
+        } else {
+            if team_slots.len() < team_size {
+                spawn_bot(false);
+            }
+            None
+        };
+
+        let Some(bot_to_remove) = bot_to_remove else {
+            continue;
+        };
+        if !bot_to_remove.is_spawned {
+            commands.entity(bot_to_remove.entity).despawn();
+            continue;
+        }
+        if let Ok((entity, _, _, is_queen, has_berry, transform, maybe_riding_on_ship)) =
+            players.get(bot_to_remove.entity)
+        {
+            remove_player(
+                &mut commands,
+                entity,
+                has_berry,
+                transform,
+                &asset_server,
+                maybe_riding_on_ship,
+            );
+            if is_queen {
+                release_join_gate(&mut commands, &mut join_gates, team);
+            }
+        }
+    }
+}
+
+fn nearest(position: Vec2, points: impl Iterator<Item = Vec2>) -> Option<Vec2> {
+    points.min_by(|a, b| {
+        a.distance_squared(position)
+            .total_cmp(&b.distance_squared(position))
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drive_bots(
+    mut bots: Query<(
+        &Player,
+        &Transform,
+        &Velocity,
+        &Team,
+        Has<Wings>,
+        Has<Queen>,
+        Has<Berry>,
+        Has<Speed>,
+        Has<RidingOnShip>,
+        Option<&Weapon>,
+        &mut ActionState<Action>,
+        &mut BotBrain,
+    )>,
+    players: Query<(&Transform, &Team, Has<Wings>, Has<RidingOnShip>), With<Player>>,
+    berries: Query<
+        &Transform,
+        (
+            With<Berry>,
+            Without<Player>,
+            Without<BerryCell>,
+            Without<Parent>, // do not chase berries held by players
+        ),
+    >,
+    empty_berry_cells: Query<(&Transform, &Team), (With<BerryCell>, Without<Berry>)>,
+    gates: Query<(&Transform, Option<&Team>, &GateKind), With<Gate>>,
+    ships: Query<(&Transform, Option<&Team>), With<Ship>>,
+    join_gates: Query<(&Transform, &JoinGate, Option<&Team>)>,
+    time: Res<Time>,
+) {
+    for (
+        player,
+        transform,
+        velocity,
+        &team,
+        has_wings,
+        is_queen,
+        has_berry,
+        is_fast,
+        is_riding_on_ship,
+        maybe_weapon,
+        mut action_state,
+        mut brain,
+    ) in &mut bots
+    {
+        brain.flap_timer.tick(time.delta());
+        let position = transform.translation.truncate();
+        let enemies = || {
+            players
+                .iter()
+                .filter(move |(_, &other_team, _, _)| other_team != team)
+        };
+
+        let mut target = None;
+        let mut jump = false;
+        let mut dive = false;
+        let mut attack = false;
+        // bots go for the join gate put out for their team, unless a human claimed another one
+        let has_join_gate = join_gates
+            .iter()
+            .any(|(.., maybe_gate_team)| maybe_gate_team == Some(&team));
+        let unclaimed_join_gate = join_gates
+            .iter()
+            .find(|(_, join_gate, maybe_gate_team)| {
+                join_gate.home == team && maybe_gate_team.is_none() && !has_join_gate
+            })
+            .map(|(gate_transform, ..)| gate_transform.translation.truncate());
+        if let Some(join_gate) = unclaimed_join_gate.filter(|_| is_queen) {
+            target = Some(join_gate);
+            jump = join_gate.y > position.y && brain.flap_timer.finished();
+        } else if has_wings {
+            // defend the snail first, then hunt workers, then go after other queens and fighters
+            let enemy_rider = enemies()
+                .find(|(_, _, _, is_riding)| *is_riding)
+                .map(|(transform, ..)| transform.translation.truncate());
+            let enemy_worker = nearest(
+                position,
+                enemies()
+                    .filter(|(_, _, has_wings, _)| !has_wings)
+                    .map(|(transform, ..)| transform.translation.truncate()),
+            );
+            let enemy_fighter = nearest(
+                position,
+                enemies()
+                    .filter(|(_, _, has_wings, _)| *has_wings)
+                    .map(|(transform, ..)| transform.translation.truncate()),
+            );
+            target = enemy_rider.or(enemy_worker).or_else(|| {
+                // fighters die when landed on, so attack them from above
+                enemy_fighter.map(|fighter| fighter + Vec2::Y * BOT_ATTACK_HEIGHT)
+            });
+            if let Some(target) = target {
+                let offset = target - position;
+                jump = offset.y > -BOT_ARRIVE_DISTANCE && brain.flap_timer.finished();
+                dive = is_queen
+                    && offset.y < -BOT_ATTACK_HEIGHT / 2.0
+                    && offset.x.abs() < BOT_DIVE_RANGE;
+                // the bot faces the target it is flying towards
+                attack = maybe_weapon.is_some_and(|weapon| {
+                    offset.x.abs() < weapon.reach() && offset.y.abs() < BOT_ATTACK_HEIGHT / 2.0
+                });
+            }
+        } else if is_riding_on_ship {
+            // stay on the ship unless someone is coming to kill us
+            jump = enemies().any(|(enemy_transform, _, has_wings, _)| {
+                has_wings
+                    && enemy_transform.translation.truncate().distance(position) < BOT_DANGER_RADIUS
+            });
+        } else if has_berry {
+            let berry_cell = nearest(
+                position,
+                empty_berry_cells
+                    .iter()
+                    .filter(|(_, &cell_team)| cell_team == team)
+                    .map(|(transform, _)| transform.translation.truncate()),
+            );
+            let gate = nearest(
+                position,
+                gates
+                    .iter()
+                    .filter(|(_, maybe_gate_team, &gate_kind)| {
+                        maybe_gate_team.map_or(true, |&gate_team| gate_team == team)
+                            && !(is_fast && gate_kind == GateKind::Speed)
+                    })
+                    .map(|(transform, ..)| transform.translation.truncate()),
+            );
+            target = match (berry_cell, gate) {
+                (Some(berry_cell), Some(gate)) => {
+                    if gate.distance(position) < berry_cell.distance(position) {
+                        Some(gate)
+                    } else {
+                        Some(berry_cell)
+                    }
+                }
+                (berry_cell, gate) => berry_cell.or(gate),
+            };
+        } else {
+            let berry = nearest(
+                position,
+                berries
+                    .iter()
+                    .map(|transform| transform.translation.truncate()),
+            );
+            let free_ship = nearest(
+                position,
+                ships
+                    .iter()
+                    .filter(|(_, maybe_ship_team)| maybe_ship_team.is_none())
+                    .map(|(transform, _)| transform.translation.truncate()),
+            );
+            target = match (berry, free_ship) {
+                (Some(berry), Some(ship)) => {
+                    if ship.distance(position) < berry.distance(position) {
+                        Some(ship)
+                    } else {
+                        Some(berry)
+                    }
+                }
+                (berry, ship) => berry.or(ship),
+            };
+        }
+
+        let mut moving = false;
+        if let Some(target) = target {
+            let offset = target - position;
+            if offset.x.abs() > BOT_ARRIVE_DISTANCE {
+                press_with_value(&mut action_state, &Action::Move, offset.x.signum());
+                moving = true;
+            }
+            if !has_wings && player.is_on_ground {
+                jump = offset.y > BOT_JUMP_HEIGHT;
+            }
+        }
+        if !moving {
+            action_state.release(&Action::Move);
+        }
+
+        // jump over whatever is blocking the way
+        if moving && velocity.linvel.x.abs() < BOT_STUCK_SPEED {
+            brain.stuck_timer.tick(time.delta());
+        } else {
+            brain.stuck_timer.reset();
+        }
+        if brain.stuck_timer.finished() {
+            brain.stuck_timer.reset();
+            jump = true;
+        }
+
+        if jump && !action_state.pressed(&Action::Jump) {
+            action_state.press(&Action::Jump);
+            brain.flap_timer.reset();
+        } else {
+            action_state.release(&Action::Jump);
+        }
+        if dive {
+            action_state.press(&Action::Dive);
+        } else {
+            action_state.release(&Action::Dive);
+        }
+        if attack && !action_state.pressed(&Action::Attack) {
+            action_state.press(&Action::Attack);
+        } else {
+            action_state.release(&Action::Attack);
+        }
+    }
+}
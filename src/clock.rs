@@ -0,0 +1,372 @@
+use bevy::prelude::*;
+
+use crate::{
+    berries::BerriesCollected,
+    map::Map,
+    player::QueenDeaths,
+    settings::GameSettings,
+    ship::{distance_to_goal, Ship},
+    teams::Team,
+    GameState, WinCondition, WinEvent, WINDOW_HEIGHT, WINDOW_TOP_Y,
+};
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchClock>()
+            .add_systems(Startup, setup)
+            .add_systems(OnEnter(GameState::Join), reset_clock)
+            .add_systems(
+                Update,
+                (
+                    run_clock.run_if(in_state(GameState::Play)),
+                    update_clock_text,
+                ),
+            );
+    }
+}
+
+/// How a game that runs out of time is decided
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tiebreak {
+    #[default]
+    MostBerries,
+    ShipClosestToGoal,
+    FewestQueenDeaths,
+    /// Play on until a team deposits a berry or loses a queen
+    SuddenDeath,
+}
+
+impl Tiebreak {
+    pub const ALL: [Tiebreak; 4] = [
+        Tiebreak::MostBerries,
+        Tiebreak::ShipClosestToGoal,
+        Tiebreak::FewestQueenDeaths,
+        Tiebreak::SuddenDeath,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Tiebreak::MostBerries => "most berries",
+            Tiebreak::ShipClosestToGoal => "ship closest to goal",
+            Tiebreak::FewestQueenDeaths => "fewest queen deaths",
+            Tiebreak::SuddenDeath => "sudden death",
+        }
+    }
+
+    /// The team that is ahead, or none if it is a tie and the game goes to overtime
+    fn leader(&self, scores: &[(Team, Score)]) -> Option<Team> {
+        let value = |score: &Score| match self {
+            Tiebreak::MostBerries => score.berries as f32,
+            Tiebreak::ShipClosestToGoal => -score.ship_distance,
+            Tiebreak::FewestQueenDeaths => -score.queen_deaths as f32,
+            Tiebreak::SuddenDeath => 0.0,
+        };
+        leader(scores.iter().map(|(team, score)| (*team, value(score))))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Score {
+    berries: i32,
+    queen_deaths: i32,
+    ship_distance: f32,
+}
+
+/// The only team with the best value, if there is one
+fn leader(values: impl Iterator<Item = (Team, f32)>) -> Option<Team> {
+    let values: Vec<(Team, f32)> = values.collect();
+    let best = values
+        .iter()
+        .map(|&(_, value)| value)
+        .fold(f32::MIN, f32::max);
+    let mut leaders = values.iter().filter(|&&(_, value)| value == best);
+    match (leaders.next(), leaders.next()) {
+        (Some(&(team, _)), None) => Some(team),
+        _ => None,
+    }
+}
+
+/// The team that is ahead since overtime started, where scoring a berry moves a team ahead and
+/// losing a queen drops it behind
+fn sudden_death_leader(
+    scores: &[(Team, Score)],
+    overtime_scores: &[(Team, Score)],
+) -> Option<Team> {
+    let gains = scores
+        .iter()
+        .zip(overtime_scores)
+        .map(|(&(team, score), (_, start))| {
+            let gain = (score.berries - start.berries) - (score.queen_deaths - start.queen_deaths);
+            (team, gain as f32)
+        });
+    leader(gains)
+}
+
+#[derive(Default, Resource)]
+struct MatchClock {
+    elapsed: f32,
+    /// The scores when overtime started, the first team to improve on them wins
+    overtime: Option<Vec<(Team, Score)>>,
+}
+
+#[derive(Component)]
+struct ClockText;
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        ClockText,
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 40.0,
+                    color: Color::WHITE,
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(
+                0.0,
+                WINDOW_TOP_Y - (WINDOW_HEIGHT / 12.0),
+                100.0,
+            )),
+            ..Default::default()
+        },
+    ));
+}
+
+fn reset_clock(mut clock: ResMut<MatchClock>) {
+    clock.elapsed = 0.0;
+    clock.overtime = None;
+}
+
+/// The score of every team in play, where the ship distance is that of the ship closest to its goal
+fn scores(
+    berries_collected: &BerriesCollected,
+    queen_deaths: &QueenDeaths,
+    ship_xs: &[f32],
+    game_settings: &GameSettings,
+    map: &Map,
+) -> Vec<(Team, Score)> {
+    game_settings
+        .teams()
+        .map(|team| {
+            let score = Score {
+                berries: berries_collected.get(team),
+                queen_deaths: queen_deaths.get(team),
+                ship_distance: ship_xs
+                    .iter()
+                    .map(|&x| distance_to_goal(x, &team, map))
+                    .fold(f32::MAX, f32::min),
+            };
+            (team, score)
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_clock(
+    mut clock: ResMut<MatchClock>,
+    mut ev_win: EventWriter<WinEvent>,
+    berries_collected: Res<BerriesCollected>,
+    queen_deaths: Res<QueenDeaths>,
+    ships: Query<&Transform, With<Ship>>,
+    game_settings: Res<GameSettings>,
+    map: Res<Map>,
+    time: Res<Time>,
+) {
+    if game_settings.match_minutes <= 0.0 {
+        return;
+    }
+    clock.elapsed += time.delta_seconds();
+    if clock.elapsed < game_settings.match_minutes * 60.0 {
+        return;
+    }
+    let ship_xs: Vec<f32> = ships
+        .iter()
+        .map(|transform| transform.translation.x)
+        .collect();
+    let scores = scores(
+        &berries_collected,
+        &queen_deaths,
+        &ship_xs,
+        &game_settings,
+        &map,
+    );
+    let (winner, tiebreak) = match &clock.overtime {
+        None => (
+            game_settings.tiebreak.leader(&scores),
+            game_settings.tiebreak,
+        ),
+        Some(overtime_scores) => (
+            sudden_death_leader(&scores, overtime_scores),
+            Tiebreak::SuddenDeath,
+        ),
+    };
+    match winner {
+        Some(team) => {
+            ev_win.send(WinEvent {
+                team,
+                win_condition: WinCondition::Time(tiebreak),
+            });
+        }
+        None if clock.overtime.is_none() => clock.overtime = Some(scores),
+        None => {}
+    }
+}
+
+fn update_clock_text(
+    mut clock_texts: Query<&mut Text, With<ClockText>>,
+    clock: Res<MatchClock>,
+    game_settings: Res<GameSettings>,
+) {
+    for mut text in &mut clock_texts {
+        text.sections[0].value = if game_settings.match_minutes <= 0.0 {
+            String::new()
+        } else if clock.overtime.is_some() {
+            String::from("Overtime")
+        } else {
+            let remaining = (game_settings.match_minutes * 60.0 - clock.elapsed)
+                .max(0.0)
+                .ceil() as i32;
+            format!("{}:{:02}", remaining / 60, remaining % 60)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(berries: i32, queen_deaths: i32, ship_distance: f32) -> Score {
+        Score {
+            berries,
+            queen_deaths,
+            ship_distance,
+        }
+    }
+
+    fn teams() -> (Team, Team, Team) {
+        let mut teams = Team::all(3);
+        (
+            teams.next().unwrap(),
+            teams.next().unwrap(),
+            teams.next().unwrap(),
+        )
+    }
+
+    #[test]
+    fn leader_needs_a_unique_best_value() {
+        let (yellow, purple, red) = teams();
+        assert_eq!(
+            leader([(yellow, 1.0), (purple, 3.0), (red, 2.0)].into_iter()),
+            Some(purple)
+        );
+        assert_eq!(
+            leader([(yellow, 3.0), (purple, 3.0), (red, 2.0)].into_iter()),
+            None
+        );
+        // a tie further down does not matter
+        assert_eq!(
+            leader([(yellow, 3.0), (purple, 1.0), (red, 1.0)].into_iter()),
+            Some(yellow)
+        );
+    }
+
+    #[test]
+    fn most_berries_wins_and_ties_go_to_overtime() {
+        let (yellow, purple, _) = teams();
+        let tiebreak = Tiebreak::MostBerries;
+        assert_eq!(
+            tiebreak.leader(&[(yellow, score(4, 0, 0.0)), (purple, score(2, 0, 0.0))]),
+            Some(yellow)
+        );
+        assert_eq!(
+            tiebreak.leader(&[(yellow, score(3, 0, 0.0)), (purple, score(3, 2, 0.0))]),
+            None
+        );
+    }
+
+    #[test]
+    fn ship_closest_to_goal_wins() {
+        let (yellow, purple, _) = teams();
+        let tiebreak = Tiebreak::ShipClosestToGoal;
+        assert_eq!(
+            tiebreak.leader(&[(yellow, score(0, 0, 300.0)), (purple, score(5, 0, 200.0))]),
+            Some(purple)
+        );
+        // a ship in the middle of the track is as far from both goals
+        assert_eq!(
+            tiebreak.leader(&[(yellow, score(0, 0, 250.0)), (purple, score(5, 0, 250.0))]),
+            None
+        );
+    }
+
+    #[test]
+    fn fewest_queen_deaths_wins() {
+        let (yellow, purple, _) = teams();
+        assert_eq!(
+            Tiebreak::FewestQueenDeaths
+                .leader(&[(yellow, score(0, 2, 0.0)), (purple, score(0, 1, 0.0))]),
+            Some(purple)
+        );
+    }
+
+    #[test]
+    fn sudden_death_always_goes_to_overtime() {
+        let (yellow, purple, _) = teams();
+        assert_eq!(
+            Tiebreak::SuddenDeath.leader(&[(yellow, score(9, 0, 0.0)), (purple, score(0, 3, 0.0))]),
+            None
+        );
+    }
+
+    #[test]
+    fn overtime_goes_to_the_first_team_to_pull_ahead() {
+        let (yellow, purple, _) = teams();
+        let start = [(yellow, score(3, 1, 0.0)), (purple, score(3, 1, 0.0))];
+        assert_eq!(sudden_death_leader(&start, &start), None);
+
+        let berry = [(yellow, score(3, 1, 0.0)), (purple, score(4, 1, 0.0))];
+        assert_eq!(sudden_death_leader(&berry, &start), Some(purple));
+
+        let queen_death = [(yellow, score(3, 2, 0.0)), (purple, score(3, 1, 0.0))];
+        assert_eq!(sudden_death_leader(&queen_death, &start), Some(purple));
+
+        // a berry for one team and a queen death for the other in the same frame
+        let both = [(yellow, score(4, 1, 0.0)), (purple, score(3, 2, 0.0))];
+        assert_eq!(sudden_death_leader(&both, &start), Some(yellow));
+    }
+
+    #[test]
+    fn scores_use_the_ship_closest_to_each_goal() {
+        let game_settings = GameSettings::default();
+        let map = Map::built_in().remove(0);
+        let (yellow, purple, _) = teams();
+        let left = map.ship.left_goal;
+        let right = map.ship.right_goal;
+        let ship_xs = [left + 10.0, right - 40.0];
+
+        let scores = scores(
+            &BerriesCollected::default(),
+            &QueenDeaths::default(),
+            &ship_xs,
+            &game_settings,
+            &map,
+        );
+
+        let distance = |team: Team| {
+            scores
+                .iter()
+                .find(|(scored, _)| *scored == team)
+                .unwrap()
+                .1
+                .ship_distance
+        };
+        assert_eq!(scores.len(), game_settings.team_count);
+        assert!((distance(yellow) - 10.0).abs() < 0.01);
+        assert!((distance(purple) - 40.0).abs() < 0.01);
+        assert_eq!(Tiebreak::ShipClosestToGoal.leader(&scores), Some(yellow));
+    }
+}
@@ -0,0 +1,416 @@
+use bevy::prelude::*;
+
+use crate::player::{body_half_size, Direction};
+
+/// What a player is, which sets the size of the boxes they fight with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Queen,
+    Fighter,
+    Worker,
+}
+
+impl Role {
+    pub fn new(is_queen: bool, has_wings: bool) -> Self {
+        match (is_queen, has_wings) {
+            (true, _) => Role::Queen,
+            (false, true) => Role::Fighter,
+            (false, false) => Role::Worker,
+        }
+    }
+
+    /// How far the hitbox sticks out past the body. Workers only reach far enough to bump.
+    fn reach(&self) -> f32 {
+        match self {
+            Role::Queen => 6.0,
+            Role::Fighter => 4.0,
+            Role::Worker => 2.0,
+        }
+    }
+
+    /// Half the size of one of the role's boxes, which are all centred on the player. The
+    /// hurtbox is the body and the hitbox reaches a little past it, so it overlaps the hurtbox
+    /// of anyone the body touches.
+    pub fn half_size(&self, combat_box: CombatBox) -> Vec2 {
+        let body = body_half_size(*self != Role::Worker);
+        match combat_box {
+            CombatBox::Hitbox => body + self.reach(),
+            CombatBox::Hurtbox => body,
+        }
+    }
+}
+
+/// A sensor collider on a player. Fights only start where the hitbox of one player overlaps the
+/// hurtbox of another.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CombatBox {
+    Hitbox,
+    Hurtbox,
+}
+
+impl CombatBox {
+    pub const ALL: [CombatBox; 2] = [CombatBox::Hitbox, CombatBox::Hurtbox];
+}
+
+/// The owners of two touching boxes as the player hitting and the player being hit, or none
+/// when it is not a hitbox touching a hurtbox
+pub fn hit_pair<T>(first: (CombatBox, T), second: (CombatBox, T)) -> Option<(T, T)> {
+    match (first, second) {
+        ((CombatBox::Hitbox, hitting), (CombatBox::Hurtbox, hit))
+        | ((CombatBox::Hurtbox, hit), (CombatBox::Hitbox, hitting)) => Some((hitting, hit)),
+        _ => None,
+    }
+}
+
+/// The normal of two overlapping boxes, pointing from the first to the second along the axis
+/// they overlap least on. An equal overlap on both axes gives the diagonal, so `resolve_combat`
+/// decides it from how the players were moving.
+pub fn overlap_normal(first: Rect, second: Rect) -> Vec2 {
+    let overlap = first.intersect(second).size();
+    let offset = second.center() - first.center();
+    let sign = Vec2::new(
+        if offset.x < 0.0 { -1.0 } else { 1.0 },
+        if offset.y < 0.0 { -1.0 } else { 1.0 },
+    );
+    if overlap.x < overlap.y {
+        Vec2::new(sign.x, 0.0)
+    } else if overlap.y < overlap.x {
+        Vec2::new(0.0, sign.y)
+    } else {
+        sign.normalize()
+    }
+}
+
+/// What decides a fight for one of the two players that touched
+#[derive(Clone, Copy, Debug)]
+pub struct Combatant {
+    pub has_wings: bool,
+    pub direction: Direction,
+    pub diving: bool,
+    pub velocity: Vec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CombatOutcome {
+    FirstDies,
+    SecondDies,
+    /// Both players bounce away from each other, the first one towards `Direction`
+    KnockBack(Direction),
+    Nothing,
+}
+
+/// Works out a collision between players of different teams from the contact normal, which
+/// points from the first player to the second. Swapping the players and flipping the normal
+/// always gives the mirrored outcome, so it does not matter which one Rapier reports first.
+pub fn resolve_combat(first: &Combatant, second: &Combatant, normal: Vec2) -> CombatOutcome {
+    let on_top = is_on_top(first, second, normal);
+    // the first player bounces away from the second one
+    let knockback = CombatOutcome::KnockBack(if normal.x > 0.0 {
+        Direction::Left
+    } else {
+        Direction::Right
+    });
+    match (first.has_wings, second.has_wings) {
+        (true, false) => CombatOutcome::SecondDies,
+        (false, true) => CombatOutcome::FirstDies,
+        (false, false) if on_top => CombatOutcome::Nothing,
+        (false, false) => knockback,
+        (true, true) if on_top => {
+            // the player underneath dies
+            if normal.y < 0.0 {
+                CombatOutcome::SecondDies
+            } else {
+                CombatOutcome::FirstDies
+            }
+        }
+        (true, true) if first.diving || second.diving => knockback,
+        (true, true) => {
+            let (left, right, first_is_left) = if normal.x > 0.0 {
+                (first, second, true)
+            } else {
+                (second, first, false)
+            };
+            // whoever has their back turned to the other player dies
+            let left_dies = match (left.direction, right.direction) {
+                (Direction::Right, Direction::Right) => false,
+                (Direction::Left, Direction::Left) => true,
+                // swords or backs meet
+                _ => return knockback,
+            };
+            if left_dies == first_is_left {
+                CombatOutcome::FirstDies
+            } else {
+                CombatOutcome::SecondDies
+            }
+        }
+    }
+}
+
+/// Whether one player came down on the other rather than hitting their side. A normal exactly
+/// on the diagonal is decided by which way the players were closing in, and an exact tie there
+/// counts as a side hit so nobody dies from it.
+fn is_on_top(first: &Combatant, second: &Combatant, normal: Vec2) -> bool {
+    if normal.y.abs() != normal.x.abs() {
+        return normal.y.abs() > normal.x.abs();
+    }
+    let closing = first.velocity - second.velocity;
+    closing.y.abs() > closing.x.abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn worker(direction: Direction) -> Combatant {
+        Combatant {
+            has_wings: false,
+            direction,
+            diving: false,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    fn queen(direction: Direction) -> Combatant {
+        Combatant {
+            has_wings: true,
+            ..worker(direction)
+        }
+    }
+
+    fn diving(combatant: Combatant) -> Combatant {
+        Combatant {
+            diving: true,
+            velocity: Vec2::new(0.0, -1200.0),
+            ..combatant
+        }
+    }
+
+    /// Checks the outcome both ways round, the mirrored pair must agree
+    fn assert_outcome(first: Combatant, second: Combatant, normal: Vec2, expected: CombatOutcome) {
+        assert_eq!(resolve_combat(&first, &second, normal), expected);
+        let mirrored = match expected {
+            CombatOutcome::FirstDies => CombatOutcome::SecondDies,
+            CombatOutcome::SecondDies => CombatOutcome::FirstDies,
+            CombatOutcome::KnockBack(Direction::Left) => CombatOutcome::KnockBack(Direction::Right),
+            CombatOutcome::KnockBack(Direction::Right) => CombatOutcome::KnockBack(Direction::Left),
+            CombatOutcome::Nothing => CombatOutcome::Nothing,
+        };
+        assert_eq!(resolve_combat(&second, &first, -normal), mirrored);
+    }
+
+    /// The box of a player at `center`
+    fn combat_box(role: Role, combat_box: CombatBox, center: Vec2) -> Rect {
+        Rect::from_center_half_size(center, role.half_size(combat_box))
+    }
+
+    #[test]
+    fn only_hitbox_on_hurtbox_is_a_hit() {
+        assert_eq!(
+            hit_pair((CombatBox::Hitbox, 1), (CombatBox::Hurtbox, 2)),
+            Some((1, 2))
+        );
+        assert_eq!(
+            hit_pair((CombatBox::Hurtbox, 1), (CombatBox::Hitbox, 2)),
+            Some((2, 1))
+        );
+        assert_eq!(
+            hit_pair((CombatBox::Hitbox, 1), (CombatBox::Hitbox, 2)),
+            None
+        );
+        assert_eq!(
+            hit_pair((CombatBox::Hurtbox, 1), (CombatBox::Hurtbox, 2)),
+            None
+        );
+    }
+
+    #[test]
+    fn touching_bodies_overlap_hitbox_and_hurtbox() {
+        let roles = [Role::Queen, Role::Fighter, Role::Worker];
+        for first in roles {
+            for second in roles {
+                // bodies side by side and one standing on the other
+                let body =
+                    first.half_size(CombatBox::Hurtbox) + second.half_size(CombatBox::Hurtbox);
+                for offset in [Vec2::new(body.x, 0.0), Vec2::new(0.0, body.y)] {
+                    let hitbox = combat_box(first, CombatBox::Hitbox, Vec2::ZERO);
+                    let hurtbox = combat_box(second, CombatBox::Hurtbox, offset);
+                    assert!(!hitbox.intersect(hurtbox).is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn queens_reach_further_than_workers() {
+        let reach =
+            |role: Role| role.half_size(CombatBox::Hitbox) - role.half_size(CombatBox::Hurtbox);
+        assert!(reach(Role::Queen).x > reach(Role::Fighter).x);
+        assert!(reach(Role::Fighter).x > reach(Role::Worker).x);
+    }
+
+    #[test]
+    fn overlap_normal_follows_the_shallow_axis() {
+        let queen = Role::Queen;
+        let hitbox = combat_box(queen, CombatBox::Hitbox, Vec2::ZERO);
+        let body = queen.half_size(CombatBox::Hurtbox) * 2.0;
+
+        let beside = combat_box(queen, CombatBox::Hurtbox, Vec2::new(-body.x, 5.0));
+        assert_eq!(overlap_normal(hitbox, beside), Vec2::NEG_X);
+
+        let below = combat_box(queen, CombatBox::Hurtbox, Vec2::new(3.0, -body.y));
+        assert_eq!(overlap_normal(hitbox, below), Vec2::NEG_Y);
+
+        // a corner that overlaps as much both ways is decided from the velocities, here a queen
+        // diving onto the corner of another one
+        let square = Rect::from_center_half_size(Vec2::ZERO, Vec2::splat(10.0));
+        let corner = Rect::from_center_half_size(Vec2::splat(18.0), Vec2::splat(10.0));
+        let normal = overlap_normal(square, corner);
+        assert_eq!(normal.x, normal.y);
+        assert_outcome(
+            queen(Direction::Right),
+            diving(queen(Direction::Left)),
+            normal,
+            CombatOutcome::FirstDies,
+        );
+    }
+
+    #[test]
+    fn queen_kills_worker_from_any_side() {
+        for normal in [Vec2::X, Vec2::NEG_X, Vec2::Y, Vec2::NEG_Y] {
+            assert_outcome(
+                queen(Direction::Right),
+                worker(Direction::Left),
+                normal,
+                CombatOutcome::SecondDies,
+            );
+        }
+    }
+
+    #[test]
+    fn workers_bump_sides() {
+        assert_outcome(
+            worker(Direction::Right),
+            worker(Direction::Left),
+            Vec2::X,
+            CombatOutcome::KnockBack(Direction::Left),
+        );
+        assert_outcome(
+            worker(Direction::Right),
+            worker(Direction::Left),
+            Vec2::NEG_Y,
+            CombatOutcome::Nothing,
+        );
+    }
+
+    #[test]
+    fn top_kill() {
+        // the second queen is below the first one
+        assert_outcome(
+            queen(Direction::Right),
+            queen(Direction::Left),
+            Vec2::new(0.3, -1.0).normalize(),
+            CombatOutcome::SecondDies,
+        );
+        // facing does not matter when landing on someone
+        assert_outcome(
+            queen(Direction::Left),
+            queen(Direction::Left),
+            Vec2::NEG_Y,
+            CombatOutcome::SecondDies,
+        );
+    }
+
+    #[test]
+    fn back_stab() {
+        // the first queen is on the left facing the back of the second
+        assert_outcome(
+            queen(Direction::Right),
+            queen(Direction::Right),
+            Vec2::X,
+            CombatOutcome::SecondDies,
+        );
+        assert_outcome(
+            queen(Direction::Left),
+            queen(Direction::Left),
+            Vec2::X,
+            CombatOutcome::FirstDies,
+        );
+    }
+
+    #[test]
+    fn sword_clash() {
+        assert_outcome(
+            queen(Direction::Right),
+            queen(Direction::Left),
+            Vec2::X,
+            CombatOutcome::KnockBack(Direction::Left),
+        );
+        // back to back is a clash too
+        assert_outcome(
+            queen(Direction::Left),
+            queen(Direction::Right),
+            Vec2::X,
+            CombatOutcome::KnockBack(Direction::Left),
+        );
+    }
+
+    #[test]
+    fn dive_clash() {
+        assert_outcome(
+            diving(queen(Direction::Right)),
+            queen(Direction::Right),
+            Vec2::X,
+            CombatOutcome::KnockBack(Direction::Left),
+        );
+        assert_outcome(
+            diving(queen(Direction::Right)),
+            diving(queen(Direction::Left)),
+            Vec2::NEG_X,
+            CombatOutcome::KnockBack(Direction::Right),
+        );
+    }
+
+    #[test]
+    fn diving_onto_a_queen_still_kills() {
+        assert_outcome(
+            diving(queen(Direction::Right)),
+            queen(Direction::Right),
+            Vec2::NEG_Y,
+            CombatOutcome::SecondDies,
+        );
+    }
+
+    #[test]
+    fn diagonal_tie_break_uses_closing_velocity() {
+        let normal = Vec2::new(1.0, -1.0).normalize();
+        // coming down fast counts as landing on top
+        assert_outcome(
+            diving(queen(Direction::Left)),
+            queen(Direction::Left),
+            normal,
+            CombatOutcome::SecondDies,
+        );
+        // flying in sideways counts as a side hit
+        let mut rushing = queen(Direction::Right);
+        rushing.velocity = Vec2::new(600.0, 0.0);
+        assert_outcome(
+            rushing,
+            queen(Direction::Right),
+            normal,
+            CombatOutcome::SecondDies,
+        );
+        assert_outcome(
+            rushing,
+            queen(Direction::Left),
+            normal,
+            CombatOutcome::KnockBack(Direction::Left),
+        );
+        // with nothing to go on it is a clash, never a death
+        assert_outcome(
+            queen(Direction::Left),
+            queen(Direction::Right),
+            normal,
+            CombatOutcome::KnockBack(Direction::Left),
+        );
+    }
+}
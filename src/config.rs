@@ -0,0 +1,32 @@
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Reads a config file saved by `save`, falling back to the default when it is missing or broken
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load<T: DeserializeOwned + Default>(path: &str) -> T {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return T::default();
+    };
+    ron::from_str(&contents).unwrap_or_else(|err| {
+        warn!("could not read {path}, using defaults: {err}");
+        T::default()
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load<T: DeserializeOwned + Default>(_path: &str) -> T {
+    T::default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save<T: Serialize>(path: &str, value: &T) {
+    let result = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())
+        .and_then(|contents| std::fs::write(path, contents).map_err(|err| err.to_string()));
+    if let Err(err) = result {
+        warn!("could not save {path}: {err}");
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save<T: Serialize>(_path: &str, _value: &T) {}
@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+
+use bevy::{input::common_conditions::input_toggle_active, prelude::*};
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    keyboard::{KeyboardControls, KeyboardLayout},
+    player::{Action, Player, PlayerController, Queen},
+};
+
+const CONTROLS_PATH: &str = "controls.ron";
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ControlsConfig::load())
+            .init_resource::<Rebinding>()
+            .add_systems(
+                Update,
+                (
+                    (show_controls, capture_rebinding)
+                        .chain()
+                        .run_if(input_toggle_active(false, KeyCode::Escape)),
+                    apply_controls.run_if(resource_changed::<ControlsConfig>),
+                ),
+            );
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GamepadControls {
+    /// Buttons for every button-like action
+    pub buttons: BTreeMap<Action, GamepadButtonType>,
+    /// How far the stick has to be pushed sideways to move
+    pub move_deadzone: f32,
+    /// How far the stick has to be pushed down for a queen to dive
+    pub dive_threshold: f32,
+}
+
+impl Default for GamepadControls {
+    fn default() -> Self {
+        Self {
+            buttons: BTreeMap::from([
+                (Action::Jump, GamepadButtonType::South),
+                (Action::Dive, GamepadButtonType::DPadDown),
+                (Action::Disconnect, GamepadButtonType::Select),
+                (Action::Ready, GamepadButtonType::Start),
+                (Action::ToggleQueen, GamepadButtonType::North),
+                (Action::Attack, GamepadButtonType::West),
+                // only workers carry berries and only fighters have weapons
+                (Action::Throw, GamepadButtonType::West),
+            ]),
+            move_deadzone: 0.5,
+            dive_threshold: 0.9,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ControlsConfig {
+    pub gamepad: GamepadControls,
+    /// Controls for specific gamepads that override the shared gamepad controls
+    pub gamepads: BTreeMap<usize, GamepadControls>,
+    pub keyboards: BTreeMap<KeyboardLayout, KeyboardControls>,
+}
+
+impl Default for ControlsConfig {
+    fn default() -> Self {
+        Self {
+            gamepad: GamepadControls::default(),
+            gamepads: BTreeMap::new(),
+            keyboards: KeyboardLayout::ALL
+                .into_iter()
+                .map(|layout| (layout, layout.default_controls()))
+                .collect(),
+        }
+    }
+}
+
+impl ControlsConfig {
+    pub fn gamepad(&self, gamepad: Gamepad) -> &GamepadControls {
+        self.gamepads.get(&gamepad.id).unwrap_or(&self.gamepad)
+    }
+
+    pub fn keyboard(&self, layout: KeyboardLayout) -> KeyboardControls {
+        self.keyboards
+            .get(&layout)
+            .cloned()
+            .unwrap_or_else(|| layout.default_controls())
+    }
+
+    /// Adds bindings for actions that did not exist when the config was saved
+    fn fill_missing(&mut self) {
+        let defaults = ControlsConfig::default();
+        for gamepad_controls in [&mut self.gamepad]
+            .into_iter()
+            .chain(self.gamepads.values_mut())
+        {
+            for (action, button) in &defaults.gamepad.buttons {
+                gamepad_controls.buttons.entry(*action).or_insert(*button);
+            }
+        }
+        for (layout, default_controls) in defaults.keyboards {
+            let keyboard_controls = self
+                .keyboards
+                .entry(layout)
+                .or_insert(default_controls.clone());
+            for (action, key) in default_controls.keys {
+                keyboard_controls.keys.entry(action).or_insert(key);
+            }
+        }
+    }
+
+    fn load() -> Self {
+        let mut controls: ControlsConfig = config::load(CONTROLS_PATH);
+        controls.fill_missing();
+        controls
+    }
+
+    fn save(&self) {
+        config::save(CONTROLS_PATH, self);
+    }
+}
+
+pub fn input_map(
+    player_controller: PlayerController,
+    is_queen: bool,
+    controls: &ControlsConfig,
+) -> InputMap<Action> {
+    let mut input_map = InputMap::default();
+    match player_controller {
+        PlayerController::Gamepad(gamepad) => {
+            let gamepad_controls = controls.gamepad(gamepad);
+            input_map.insert(
+                Action::Move,
+                SingleAxis::symmetric(GamepadAxisType::LeftStickX, gamepad_controls.move_deadzone),
+            );
+            input_map.insert(Action::Move, VirtualAxis::horizontal_dpad());
+            for (&action, &button) in &gamepad_controls.buttons {
+                if action != Action::Dive || is_queen {
+                    input_map.insert(action, button);
+                }
+            }
+            if is_queen {
+                input_map.insert(
+                    Action::Dive,
+                    SingleAxis::negative_only(
+                        GamepadAxisType::LeftStickY,
+                        -gamepad_controls.dive_threshold,
+                    ),
+                );
+            }
+            input_map.set_gamepad(gamepad);
+        }
+        PlayerController::Keyboard { layout } => {
+            let keyboard_controls = controls.keyboard(layout);
+            input_map.insert(
+                Action::Move,
+                VirtualAxis::from_keys(keyboard_controls.move_left, keyboard_controls.move_right),
+            );
+            for (&action, &key) in &keyboard_controls.keys {
+                if action != Action::Dive || is_queen {
+                    input_map.insert(action, key);
+                }
+            }
+        }
+        PlayerController::Midi { .. }
+        | PlayerController::Touch { .. }
+        | PlayerController::Bot { .. } => (),
+    }
+    input_map
+}
+
+fn apply_controls(
+    mut players: Query<(&Player, &mut InputMap<Action>, Has<Queen>)>,
+    controls: Res<ControlsConfig>,
+) {
+    for (player, mut player_input_map, is_queen) in &mut players {
+        *player_input_map = input_map(player.player_controller, is_queen, &controls);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ControlsTab {
+    Gamepad(Option<usize>),
+    Keyboard(KeyboardLayout),
+}
+
+impl Default for ControlsTab {
+    fn default() -> Self {
+        ControlsTab::Gamepad(None)
+    }
+}
+
+impl ControlsTab {
+    fn name(&self) -> String {
+        match self {
+            ControlsTab::Gamepad(None) => String::from("all gamepads"),
+            ControlsTab::Gamepad(Some(id)) => format!("gamepad {id}"),
+            ControlsTab::Keyboard(layout) => format!("keyboard {:?}", layout).to_lowercase(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Binding {
+    MoveLeft,
+    MoveRight,
+    JoinYellow,
+    JoinPurple,
+    Action(Action),
+}
+
+impl Binding {
+    fn name(&self) -> String {
+        match self {
+            Binding::MoveLeft => String::from("move left"),
+            Binding::MoveRight => String::from("move right"),
+            Binding::JoinYellow => String::from("join left"),
+            Binding::JoinPurple => String::from("join right"),
+            Binding::Action(action) => format!("{:?}", action).to_lowercase(),
+        }
+    }
+}
+
+/// The binding that is waiting for the next button or key press
+#[derive(Resource, Default)]
+struct Rebinding(Option<(ControlsTab, Binding)>);
+
+fn show_controls(
+    mut contexts: EguiContexts,
+    mut controls: ResMut<ControlsConfig>,
+    mut rebinding: ResMut<Rebinding>,
+    mut tab: Local<ControlsTab>,
+    gamepads: Res<Gamepads>,
+) {
+    let mut changed = false;
+    egui::Window::new("Controls").show(contexts.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("controller")
+            .selected_text(tab.name())
+            .show_ui(ui, |ui| {
+                let tabs = [ControlsTab::Gamepad(None)]
+                    .into_iter()
+                    .chain(
+                        gamepads
+                            .iter()
+                            .map(|gamepad| ControlsTab::Gamepad(Some(gamepad.id))),
+                    )
+                    .chain(KeyboardLayout::ALL.map(ControlsTab::Keyboard));
+                for option in tabs {
+                    ui.selectable_value(&mut *tab, option, option.name());
+                }
+            });
+
+        let mut rebind = None;
+        let mut binding_button = |ui: &mut egui::Ui, binding: Binding, current: String| {
+            ui.label(binding.name());
+            let text = if rebinding.0 == Some((*tab, binding)) {
+                String::from("press to bind...")
+            } else {
+                current
+            };
+            if ui.button(text).clicked() {
+                rebind = Some(binding);
+            }
+            ui.end_row();
+        };
+        match *tab {
+            ControlsTab::Gamepad(maybe_id) => {
+                if let Some(id) = maybe_id {
+                    let mut custom = controls.gamepads.contains_key(&id);
+                    if ui.checkbox(&mut custom, "custom controls").changed() {
+                        if custom {
+                            let shared_controls = controls.gamepad.clone();
+                            controls.gamepads.insert(id, shared_controls);
+                        } else {
+                            controls.gamepads.remove(&id);
+                        }
+                        changed = true;
+                    }
+                }
+                let gamepad_controls = match maybe_id {
+                    Some(id) => controls.gamepads.get_mut(&id),
+                    None => Some(&mut controls.gamepad),
+                };
+                // gamepads without custom controls use the shared ones
+                if let Some(gamepad_controls) = gamepad_controls {
+                    egui::Grid::new("gamepad bindings").show(ui, |ui| {
+                        for (&action, button) in &gamepad_controls.buttons {
+                            binding_button(ui, Binding::Action(action), format!("{:?}", button));
+                        }
+                    });
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut gamepad_controls.move_deadzone, 0.05..=0.95)
+                                .text("move deadzone"),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut gamepad_controls.dive_threshold, 0.1..=1.0)
+                                .text("dive threshold"),
+                        )
+                        .changed();
+                }
+            }
+            ControlsTab::Keyboard(layout) => {
+                let keyboard_controls = controls.keyboard(layout);
+                egui::Grid::new("keyboard bindings").show(ui, |ui| {
+                    let bindings = [
+                        (Binding::MoveLeft, keyboard_controls.move_left),
+                        (Binding::MoveRight, keyboard_controls.move_right),
+                        (Binding::JoinYellow, keyboard_controls.join_yellow),
+                        (Binding::JoinPurple, keyboard_controls.join_purple),
+                    ]
+                    .into_iter()
+                    .chain(
+                        keyboard_controls
+                            .keys
+                            .iter()
+                            .map(|(&action, &key)| (Binding::Action(action), key)),
+                    );
+                    for (binding, key) in bindings {
+                        binding_button(ui, binding, format!("{:?}", key));
+                    }
+                });
+            }
+        }
+        if ui.button("reset to defaults").clicked() {
+            match *tab {
+                ControlsTab::Gamepad(None) => controls.gamepad = GamepadControls::default(),
+                ControlsTab::Gamepad(Some(id)) => {
+                    controls.gamepads.insert(id, GamepadControls::default());
+                }
+                ControlsTab::Keyboard(layout) => {
+                    controls.keyboards.insert(layout, layout.default_controls());
+                }
+            }
+            changed = true;
+        }
+        if let Some(binding) = rebind {
+            rebinding.0 = Some((*tab, binding));
+        }
+    });
+    if changed {
+        controls.save();
+    }
+}
+
+fn capture_rebinding(
+    mut rebinding: ResMut<Rebinding>,
+    mut controls: ResMut<ControlsConfig>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+) {
+    let Some((tab, binding)) = rebinding.0 else {
+        return;
+    };
+    match (tab, binding) {
+        (ControlsTab::Gamepad(maybe_id), Binding::Action(action)) => {
+            let Some(pressed) = gamepad_buttons
+                .get_just_pressed()
+                .find(|button| maybe_id.map_or(true, |id| button.gamepad.id == id))
+            else {
+                return;
+            };
+            let gamepad_controls = match maybe_id {
+                Some(id) => controls.gamepads.entry(id).or_default(),
+                None => &mut controls.gamepad,
+            };
+            gamepad_controls.buttons.insert(action, pressed.button_type);
+        }
+        (ControlsTab::Keyboard(layout), binding) => {
+            let Some(&pressed) = keys.get_just_pressed().next() else {
+                return;
+            };
+            let keyboard_controls = controls
+                .keyboards
+                .entry(layout)
+                .or_insert_with(|| layout.default_controls());
+            match binding {
+                Binding::MoveLeft => keyboard_controls.move_left = pressed,
+                Binding::MoveRight => keyboard_controls.move_right = pressed,
+                Binding::JoinYellow => keyboard_controls.join_yellow = pressed,
+                Binding::JoinPurple => keyboard_controls.join_purple = pressed,
+                Binding::Action(action) => {
+                    keyboard_controls.keys.insert(action, pressed);
+                }
+            }
+        }
+        // gamepads only rebind actions
+        (ControlsTab::Gamepad(_), _) => (),
+    }
+    rebinding.0 = None;
+    controls.save();
+}
@@ -0,0 +1,832 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+
+use crate::{
+    berries::{berry_cell_positions, RespawnBerriesEvent, BERRY_RENDER_RADIUS},
+    gates::{GateKind, GATE_HEIGHT, GATE_WIDTH},
+    hive::{HiveRole, HIVE_RADIUS},
+    map::{GateLayout, HiveLayout, Map, MapLibrary, PlatformLayout},
+    player::Direction,
+    settings::GameSettings,
+    teams::{Team, TEAMS},
+    weapons::Weapon,
+    GameState,
+};
+
+/// How big the handles of pieces without a size of their own are
+const HANDLE_SIZE: f32 = 30.0;
+const GRID_SIZE: f32 = 8.0;
+/// How far apart two pieces can be and still count as mirror images of each other
+const MIRROR_TOLERANCE: f32 = 1.0;
+const NEW_PLATFORM_SIZE: Vec2 = Vec2::new(96.0, 20.0);
+const MAX_BERRY_ROWS: usize = 6;
+const SELECTED_COLOR: Color = Color::CYAN;
+const MIRROR_COLOR: Color = Color::PINK;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Editor>()
+            .add_systems(
+                Update,
+                (
+                    show_editor,
+                    edit_with_mouse,
+                    delete_with_keyboard,
+                    preview_berries,
+                    draw_map,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Editor)),
+            )
+            .add_systems(OnExit(GameState::Editor), deselect);
+    }
+}
+
+/// What a click on the map does
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Tool {
+    /// Picks the piece under the cursor to drag it around
+    #[default]
+    Select,
+    Platform,
+    Gate(GateKind),
+    BerryBunch,
+}
+
+impl Tool {
+    const ALL: [Tool; 5] = [
+        Tool::Select,
+        Tool::Platform,
+        Tool::Gate(GateKind::Warrior),
+        Tool::Gate(GateKind::Speed),
+        Tool::BerryBunch,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Tool::Select => "select",
+            Tool::Platform => "platform",
+            Tool::Gate(GateKind::Warrior) => "warrior gate",
+            Tool::Gate(GateKind::Speed) => "speed gate",
+            Tool::BerryBunch => "berry bunch",
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Editor {
+    tool: Tool,
+    selected: Option<Piece>,
+    /// Where the selected piece is from the cursor while it is dragged
+    grab: Option<Vec2>,
+    /// Whether edits are copied to the mirror image of a piece on the other side of the map
+    mirror: bool,
+    snap: bool,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            tool: Tool::default(),
+            selected: None,
+            grab: None,
+            mirror: true,
+            snap: true,
+        }
+    }
+}
+
+impl Editor {
+    fn snapped(&self, point: Vec2) -> Vec2 {
+        if self.snap {
+            (point / GRID_SIZE).round() * GRID_SIZE
+        } else {
+            point
+        }
+    }
+}
+
+/// A part of the map that can be picked in the editor, by where it is in the map's lists
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Piece {
+    Platform(usize),
+    JoinPlatform(usize),
+    Gate(usize),
+    BerryBunch(usize),
+    BerryCells(Team),
+    Hive(Team, usize),
+    JoinGate(Team),
+    ShipStart,
+    ShipGoal(Direction),
+}
+
+impl Piece {
+    /// Every piece of the map, the small ones first so they are picked over the platforms
+    /// they sit on
+    fn all(map: &Map) -> Vec<Piece> {
+        let mut pieces = vec![
+            Piece::ShipStart,
+            Piece::ShipGoal(Direction::Left),
+            Piece::ShipGoal(Direction::Right),
+        ];
+        for team in Team::all(TEAMS.len()) {
+            pieces.push(Piece::BerryCells(team));
+            pieces.push(Piece::JoinGate(team));
+            pieces.extend((0..map.team(team).hives.len()).map(|index| Piece::Hive(team, index)));
+        }
+        pieces.extend((0..map.berry_bunches.len()).map(Piece::BerryBunch));
+        pieces.extend((0..map.gates.len()).map(Piece::Gate));
+        pieces.extend((0..map.join_platforms.len()).map(Piece::JoinPlatform));
+        pieces.extend((0..map.platforms.len()).map(Piece::Platform));
+        pieces
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Piece::Platform(_) => "platform".to_string(),
+            Piece::JoinPlatform(_) => "lobby platform".to_string(),
+            Piece::Gate(_) => "gate".to_string(),
+            Piece::BerryBunch(_) => "berry bunch".to_string(),
+            Piece::BerryCells(team) => format!("{} berry cells", team.name().to_lowercase()),
+            Piece::Hive(team, _) => format!("{} hive", team.name().to_lowercase()),
+            Piece::JoinGate(team) => format!("{} join gate", team.name().to_lowercase()),
+            Piece::ShipStart => "ship".to_string(),
+            Piece::ShipGoal(Direction::Left) => "left ship goal".to_string(),
+            Piece::ShipGoal(Direction::Right) => "right ship goal".to_string(),
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Piece::Platform(_) => Color::GRAY,
+            Piece::JoinPlatform(_) => Color::BLACK,
+            Piece::Gate(_) => Color::ORANGE,
+            Piece::BerryBunch(_) => Color::RED,
+            Piece::BerryCells(team) | Piece::Hive(team, _) | Piece::JoinGate(team) => team.color(),
+            Piece::ShipStart | Piece::ShipGoal(_) => Color::WHITE,
+        }
+    }
+
+    /// Where the piece is in its list, pieces that are not in a list have no index
+    fn index(&self) -> usize {
+        match *self {
+            Piece::Platform(index)
+            | Piece::JoinPlatform(index)
+            | Piece::Gate(index)
+            | Piece::BerryBunch(index)
+            | Piece::Hive(_, index) => index,
+            _ => 0,
+        }
+    }
+
+    fn position(&self, map: &Map) -> Vec2 {
+        match *self {
+            Piece::Platform(index) => map.platforms[index].position,
+            Piece::JoinPlatform(index) => map.join_platforms[index].position,
+            Piece::Gate(index) => map.gates[index].position,
+            Piece::BerryBunch(index) => map.berry_bunches[index],
+            Piece::BerryCells(team) => map.team(team).berry_cells.position,
+            Piece::Hive(team, index) => map.team(team).hives[index].position,
+            Piece::JoinGate(team) => map.team(team).join_gate,
+            Piece::ShipStart => map.ship.start,
+            Piece::ShipGoal(Direction::Left) => Vec2::new(map.ship.left_goal, map.ship.start.y),
+            Piece::ShipGoal(Direction::Right) => Vec2::new(map.ship.right_goal, map.ship.start.y),
+        }
+    }
+
+    fn set_position(&self, map: &mut Map, position: Vec2) {
+        match *self {
+            Piece::Platform(index) => map.platforms[index].position = position,
+            Piece::JoinPlatform(index) => map.join_platforms[index].position = position,
+            Piece::Gate(index) => map.gates[index].position = position,
+            Piece::BerryBunch(index) => map.berry_bunches[index] = position,
+            Piece::BerryCells(team) => map.team_mut(team).berry_cells.position = position,
+            Piece::Hive(team, index) => map.team_mut(team).hives[index].position = position,
+            Piece::JoinGate(team) => map.team_mut(team).join_gate = position,
+            Piece::ShipStart => map.ship.start = position,
+            // the goals stay on the ship's track
+            Piece::ShipGoal(Direction::Left) => map.ship.left_goal = position.x,
+            Piece::ShipGoal(Direction::Right) => map.ship.right_goal = position.x,
+        }
+    }
+
+    fn size(&self, map: &Map) -> Vec2 {
+        match *self {
+            Piece::Platform(index) => map.platforms[index].size,
+            Piece::JoinPlatform(index) => map.join_platforms[index].size,
+            Piece::Gate(_) | Piece::JoinGate(_) => Vec2::new(GATE_WIDTH, GATE_HEIGHT),
+            Piece::Hive(..) => Vec2::splat(HIVE_RADIUS * 2.0),
+            _ => Vec2::splat(HANDLE_SIZE),
+        }
+    }
+
+    /// Only platforms can be resized, everything else has the size of its sprite
+    fn resize(&self, map: &mut Map, size: Vec2) {
+        match *self {
+            Piece::Platform(index) => map.platforms[index].size = size,
+            Piece::JoinPlatform(index) => map.join_platforms[index].size = size,
+            _ => {}
+        }
+    }
+
+    fn contains(&self, map: &Map, point: Vec2) -> bool {
+        let offset = (point - self.position(map)).abs();
+        let half_size = self.size(map) / 2.0;
+        offset.x <= half_size.x && offset.y <= half_size.y
+    }
+
+    /// Every team keeps its berry cells, join gate and at least one hive, and there is
+    /// always one ship
+    fn can_remove(&self, map: &Map) -> bool {
+        match *self {
+            Piece::Platform(_) | Piece::JoinPlatform(_) | Piece::Gate(_) | Piece::BerryBunch(_) => {
+                true
+            }
+            Piece::Hive(team, _) => map.team(team).hives.len() > 1,
+            Piece::BerryCells(_) | Piece::JoinGate(_) | Piece::ShipStart | Piece::ShipGoal(_) => {
+                false
+            }
+        }
+    }
+
+    fn remove(&self, map: &mut Map) {
+        match *self {
+            Piece::Platform(index) => {
+                map.platforms.remove(index);
+            }
+            Piece::JoinPlatform(index) => {
+                map.join_platforms.remove(index);
+            }
+            Piece::Gate(index) => {
+                map.gates.remove(index);
+            }
+            Piece::BerryBunch(index) => {
+                map.berry_bunches.remove(index);
+            }
+            Piece::Hive(team, index) => {
+                map.team_mut(team).hives.remove(index);
+            }
+            Piece::BerryCells(_) | Piece::JoinGate(_) | Piece::ShipStart | Piece::ShipGoal(_) => {}
+        }
+    }
+
+    /// The piece on the other side of the map that mirrors this one.
+    /// Team pieces mirror the same piece of the team in the same spot on the other side.
+    fn mirror(&self, map: &Map) -> Option<Piece> {
+        match *self {
+            Piece::BerryCells(team) => team.mirrored().map(Piece::BerryCells),
+            Piece::JoinGate(team) => team.mirrored().map(Piece::JoinGate),
+            Piece::Hive(team, index) => team
+                .mirrored()
+                .filter(|&other_team| index < map.team(other_team).hives.len())
+                .map(|other_team| Piece::Hive(other_team, index)),
+            Piece::ShipStart => None,
+            Piece::ShipGoal(Direction::Left) => Some(Piece::ShipGoal(Direction::Right)),
+            Piece::ShipGoal(Direction::Right) => Some(Piece::ShipGoal(Direction::Left)),
+            piece => {
+                let mirrored = mirrored(piece.position(map));
+                Piece::all(map).into_iter().find(|&other| {
+                    other != piece
+                        && std::mem::discriminant(&other) == std::mem::discriminant(&piece)
+                        && other.position(map).distance(mirrored) < MIRROR_TOLERANCE
+                })
+            }
+        }
+    }
+}
+
+fn mirrored(position: Vec2) -> Vec2 {
+    Vec2::new(-position.x, position.y)
+}
+
+/// Moves a piece, and its mirror image to the other side with it
+fn move_piece(map: &mut Map, piece: Piece, position: Vec2, mirror: bool) {
+    let partner = mirror.then(|| piece.mirror(map)).flatten();
+    // the ship has to start in the middle for the map to stay fair
+    let position = if mirror && piece == Piece::ShipStart {
+        Vec2::new(0.0, position.y)
+    } else {
+        position
+    };
+    piece.set_position(map, position);
+    if let Some(partner) = partner {
+        partner.set_position(map, mirrored(position));
+    }
+}
+
+fn resize_piece(map: &mut Map, piece: Piece, size: Vec2, mirror: bool) {
+    if let Some(partner) = mirror.then(|| piece.mirror(map)).flatten() {
+        partner.resize(map, size);
+    }
+    piece.resize(map, size);
+}
+
+/// Removes a piece and its mirror image, returns whether anything was removed
+fn remove_piece(map: &mut Map, piece: Piece, mirror: bool) -> bool {
+    if !piece.can_remove(map) {
+        return false;
+    }
+    let partner = mirror
+        .then(|| piece.mirror(map))
+        .flatten()
+        .filter(|partner| partner.can_remove(map));
+    let mut pieces: Vec<Piece> = std::iter::once(piece).chain(partner).collect();
+    // removing a piece moves the ones after it in the same list, so the later one goes first
+    pieces.sort_by_key(|piece| std::cmp::Reverse(piece.index()));
+    for piece in pieces {
+        piece.remove(map);
+    }
+    true
+}
+
+/// Places a new piece, and its mirror image unless it sits in the middle
+fn add_piece(map: &mut Map, tool: Tool, position: Vec2, mirror: bool) -> Option<Piece> {
+    let mut positions = vec![position];
+    if mirror && position.x.abs() >= MIRROR_TOLERANCE {
+        positions.push(mirrored(position));
+    }
+    match tool {
+        Tool::Select => None,
+        Tool::Platform => {
+            let index = map.platforms.len();
+            map.platforms
+                .extend(positions.into_iter().map(|position| PlatformLayout {
+                    position,
+                    size: NEW_PLATFORM_SIZE,
+                }));
+            Some(Piece::Platform(index))
+        }
+        Tool::Gate(kind) => {
+            let index = map.gates.len();
+            map.gates
+                .extend(positions.into_iter().map(|position| GateLayout {
+                    position,
+                    kind,
+                    weapon: None,
+                }));
+            Some(Piece::Gate(index))
+        }
+        Tool::BerryBunch => {
+            let index = map.berry_bunches.len();
+            map.berry_bunches.extend(positions);
+            Some(Piece::BerryBunch(index))
+        }
+    }
+}
+
+fn cursor_position(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec2> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (camera, camera_transform) = cameras.get_single().ok()?;
+    camera.viewport_to_world_2d(camera_transform, cursor)
+}
+
+/// Edits a copy of the map, so the map only counts as changed when something was edited
+fn show_editor(
+    mut contexts: EguiContexts,
+    mut editor: ResMut<Editor>,
+    mut map: ResMut<Map>,
+    mut library: ResMut<MapLibrary>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let mut edited = map.clone();
+    egui::Window::new("Editor").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("name");
+            ui.text_edit_singleline(&mut edited.name);
+        });
+        ui.horizontal_wrapped(|ui| {
+            for tool in Tool::ALL {
+                ui.selectable_value(&mut editor.tool, tool, tool.label());
+            }
+        });
+        ui.checkbox(&mut editor.mirror, "mirror to the other side");
+        ui.checkbox(&mut editor.snap, "snap to grid");
+        ui.label("click to place or pick, drag to move, shift-drag to resize, delete to remove");
+
+        if let Some(piece) = editor.selected {
+            ui.separator();
+            ui.label(piece.label());
+            let mirror = editor.mirror;
+            let partner = mirror.then(|| piece.mirror(&edited)).flatten();
+
+            let mut position = piece.position(&edited);
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut position.x).prefix("x "));
+                ui.add(egui::DragValue::new(&mut position.y).prefix("y "));
+            });
+            if position != piece.position(&edited) {
+                move_piece(&mut edited, piece, position, mirror);
+            }
+
+            match piece {
+                Piece::Platform(_) | Piece::JoinPlatform(_) => {
+                    let mut size = piece.size(&edited);
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut size.x)
+                                .prefix("w ")
+                                .clamp_range(1.0..=f32::MAX),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut size.y)
+                                .prefix("h ")
+                                .clamp_range(1.0..=f32::MAX),
+                        );
+                    });
+                    if size != piece.size(&edited) {
+                        resize_piece(&mut edited, piece, size, mirror);
+                    }
+                }
+                Piece::Gate(index) => {
+                    let gate = &mut edited.gates[index];
+                    egui::ComboBox::from_label("kind")
+                        .selected_text(format!("{:?}", gate.kind).to_lowercase())
+                        .show_ui(ui, |ui| {
+                            for kind in GateKind::ALL {
+                                ui.selectable_value(
+                                    &mut gate.kind,
+                                    kind,
+                                    format!("{:?}", kind).to_lowercase(),
+                                );
+                            }
+                        });
+                    if gate.kind == GateKind::Warrior {
+                        egui::ComboBox::from_label("weapon")
+                            .selected_text(
+                                gate.weapon.map_or("from settings".to_string(), |weapon| {
+                                    format!("{:?}", weapon).to_lowercase()
+                                }),
+                            )
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut gate.weapon, None, "from settings");
+                                for weapon in Weapon::ALL {
+                                    ui.selectable_value(
+                                        &mut gate.weapon,
+                                        Some(weapon),
+                                        format!("{:?}", weapon).to_lowercase(),
+                                    );
+                                }
+                            });
+                    } else {
+                        gate.weapon = None;
+                    }
+                    let gate = *gate;
+                    if let Some(Piece::Gate(other)) = partner {
+                        edited.gates[other].kind = gate.kind;
+                        edited.gates[other].weapon = gate.weapon;
+                    }
+                }
+                Piece::BerryCells(team) => {
+                    let berry_cells = &mut edited.team_mut(team).berry_cells;
+                    ui.add(
+                        egui::Slider::new(&mut berry_cells.rows, 1..=MAX_BERRY_ROWS).text("rows"),
+                    );
+                    let rows = berry_cells.rows;
+                    if let Some(Piece::BerryCells(other_team)) = partner {
+                        edited.team_mut(other_team).berry_cells.rows = rows;
+                    }
+                }
+                Piece::Hive(team, index) => {
+                    let hive = &mut edited.team_mut(team).hives[index];
+                    egui::ComboBox::from_label("respawns")
+                        .selected_text(format!("{:?}", hive.role).to_lowercase())
+                        .show_ui(ui, |ui| {
+                            for role in HiveRole::ALL {
+                                ui.selectable_value(
+                                    &mut hive.role,
+                                    role,
+                                    format!("{:?}", role).to_lowercase(),
+                                );
+                            }
+                        });
+                    let hive = *hive;
+                    if let Some(Piece::Hive(other_team, other)) = partner {
+                        edited.team_mut(other_team).hives[other].role = hive.role;
+                    }
+                    if ui.button("add hive").clicked() {
+                        let new_hive = HiveLayout {
+                            position: hive.position + Vec2::X * HIVE_RADIUS * 2.0,
+                            role: hive.role,
+                        };
+                        edited.team_mut(team).hives.push(new_hive);
+                        if let Some(other_team) = mirror.then(|| team.mirrored()).flatten() {
+                            edited.team_mut(other_team).hives.push(HiveLayout {
+                                position: mirrored(new_hive.position),
+                                ..new_hive
+                            });
+                        }
+                        editor.selected =
+                            Some(Piece::Hive(team, edited.team(team).hives.len() - 1));
+                    }
+                }
+                _ => {}
+            }
+
+            if piece.can_remove(&edited)
+                && ui.button("delete").clicked()
+                && remove_piece(&mut edited, piece, mirror)
+            {
+                editor.selected = None;
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("save").clicked() {
+                edited.save();
+                library.insert(edited.clone());
+            }
+            if ui.button("test play").clicked() {
+                next_state.set(GameState::Join);
+            }
+        });
+    });
+    map.set_if_neq(edited);
+}
+
+fn edit_with_mouse(
+    mut editor: ResMut<Editor>,
+    mut map: ResMut<Map>,
+    mut contexts: EguiContexts,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    if !mouse.pressed(MouseButton::Left) {
+        editor.grab = None;
+        return;
+    }
+    let Some(cursor) = cursor_position(&windows, &cameras) else {
+        return;
+    };
+
+    if mouse.just_pressed(MouseButton::Left) {
+        // clicks on the editor window are not meant for the map
+        if contexts.ctx_mut().is_pointer_over_area() {
+            return;
+        }
+        editor.selected = match editor.tool {
+            Tool::Select => Piece::all(&map)
+                .into_iter()
+                .find(|piece| piece.contains(&map, cursor)),
+            tool => add_piece(&mut map, tool, editor.snapped(cursor), editor.mirror),
+        };
+        editor.grab = editor.selected.map(|piece| piece.position(&map) - cursor);
+        return;
+    }
+
+    let (Some(piece), Some(grab)) = (editor.selected, editor.grab) else {
+        return;
+    };
+    if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        let size = editor
+            .snapped((cursor - piece.position(&map)).abs() * 2.0)
+            .max(Vec2::ONE);
+        if size != piece.size(&map) {
+            resize_piece(&mut map, piece, size, editor.mirror);
+        }
+    } else {
+        let position = editor.snapped(cursor + grab);
+        if position != piece.position(&map) {
+            move_piece(&mut map, piece, position, editor.mirror);
+        }
+    }
+}
+
+fn delete_with_keyboard(
+    mut editor: ResMut<Editor>,
+    mut map: ResMut<Map>,
+    mut contexts: EguiContexts,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    if !keyboard_input.any_just_pressed([KeyCode::Delete, KeyCode::Backspace])
+        || contexts.ctx_mut().wants_keyboard_input()
+    {
+        return;
+    }
+    if let Some(piece) = editor.selected {
+        if remove_piece(&mut map, piece, editor.mirror) {
+            editor.selected = None;
+        }
+    }
+}
+
+/// Berries are put back where the edited map hangs them, so they show up like in a game
+fn preview_berries(map: Res<Map>, mut respawn_berries_ev: EventWriter<RespawnBerriesEvent>) {
+    if map.is_changed() {
+        respawn_berries_ev.send(RespawnBerriesEvent);
+    }
+}
+
+/// Outlines every piece, since most of them have no sprite while the map is edited
+fn draw_map(
+    mut gizmos: Gizmos,
+    map: Res<Map>,
+    editor: Res<Editor>,
+    game_settings: Res<GameSettings>,
+) {
+    let partner = editor
+        .selected
+        .filter(|_| editor.mirror)
+        .and_then(|piece| piece.mirror(&map));
+    for piece in Piece::all(&map) {
+        let color = if Some(piece) == editor.selected {
+            SELECTED_COLOR
+        } else if Some(piece) == partner {
+            MIRROR_COLOR
+        } else {
+            piece.color()
+        };
+        gizmos.rect_2d(piece.position(&map), 0.0, piece.size(&map), color);
+    }
+    for team in Team::all(TEAMS.len()) {
+        let berry_cells = &map.team(team).berry_cells;
+        for cell in berry_cell_positions(team, berry_cells, game_settings.berries_to_win) {
+            gizmos.circle_2d(cell, BERRY_RENDER_RADIUS, team.color());
+        }
+    }
+    gizmos.line_2d(
+        Piece::ShipGoal(Direction::Left).position(&map),
+        Piece::ShipGoal(Direction::Right).position(&map),
+        Color::WHITE,
+    );
+}
+
+fn deselect(mut editor: ResMut<Editor>) {
+    editor.selected = None;
+    editor.grab = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day() -> Map {
+        Map::built_in().remove(0)
+    }
+
+    fn teams() -> (Team, Team) {
+        let mut teams = Team::all(2);
+        (teams.next().unwrap(), teams.next().unwrap())
+    }
+
+    #[test]
+    fn mirrored_add_places_both_sides() {
+        let mut map = day();
+        let platforms = map.platforms.len();
+
+        let piece = add_piece(&mut map, Tool::Platform, Vec2::new(100.0, 40.0), true).unwrap();
+
+        assert_eq!(map.platforms.len(), platforms + 2);
+        assert_eq!(piece.position(&map), Vec2::new(100.0, 40.0));
+        let partner = piece.mirror(&map).unwrap();
+        assert_eq!(partner.position(&map), Vec2::new(-100.0, 40.0));
+        assert_eq!(partner.mirror(&map), Some(piece));
+    }
+
+    #[test]
+    fn add_in_the_middle_places_one() {
+        let mut map = day();
+        let gates = map.gates.len();
+
+        add_piece(
+            &mut map,
+            Tool::Gate(GateKind::Speed),
+            Vec2::new(0.0, 40.0),
+            true,
+        );
+        add_piece(
+            &mut map,
+            Tool::Gate(GateKind::Speed),
+            Vec2::new(64.0, 40.0),
+            false,
+        );
+
+        assert_eq!(map.gates.len(), gates + 2);
+    }
+
+    #[test]
+    fn mirrored_move_drags_the_partner_along() {
+        let mut map = day();
+        let piece = add_piece(
+            &mut map,
+            Tool::Gate(GateKind::Warrior),
+            Vec2::new(200.0, 0.0),
+            true,
+        )
+        .unwrap();
+        let partner = piece.mirror(&map).unwrap();
+
+        move_piece(&mut map, piece, Vec2::new(248.0, -80.0), true);
+
+        assert_eq!(piece.position(&map), Vec2::new(248.0, -80.0));
+        assert_eq!(partner.position(&map), Vec2::new(-248.0, -80.0));
+        assert_eq!(piece.mirror(&map), Some(partner));
+
+        move_piece(&mut map, Piece::ShipStart, Vec2::new(30.0, -400.0), true);
+        assert_eq!(map.ship.start, Vec2::new(0.0, -400.0));
+    }
+
+    #[test]
+    fn mirrored_resize_matches_the_partner() {
+        let mut map = day();
+        let piece = Piece::Platform(1);
+        let partner = piece.mirror(&map).unwrap();
+
+        resize_piece(&mut map, piece, Vec2::new(200.0, 30.0), true);
+
+        assert_eq!(partner.size(&map), Vec2::new(200.0, 30.0));
+    }
+
+    #[test]
+    fn mirrored_delete_removes_the_pair_from_one_list() {
+        let mut map = day();
+        let before = map.platforms.clone();
+        // the partner comes first in the list, so it has to be removed last
+        let piece = Piece::Platform(2);
+        let Some(Piece::Platform(partner)) = piece.mirror(&map) else {
+            panic!("platform {:?} has no mirror image", piece);
+        };
+        assert!(partner < 2);
+
+        assert!(remove_piece(&mut map, piece, true));
+
+        let expected: Vec<PlatformLayout> = before
+            .into_iter()
+            .enumerate()
+            .filter(|&(index, _)| index != 2 && index != partner)
+            .map(|(_, platform)| platform)
+            .collect();
+        assert_eq!(map.platforms, expected);
+    }
+
+    #[test]
+    fn delete_without_mirror_keeps_the_partner() {
+        let mut map = day();
+        let berry_bunches = map.berry_bunches.len();
+
+        assert!(remove_piece(&mut map, Piece::BerryBunch(0), false));
+
+        assert_eq!(map.berry_bunches.len(), berry_bunches - 1);
+    }
+
+    #[test]
+    fn last_hive_of_a_team_stays() {
+        let mut map = day();
+        let (yellow, purple) = teams();
+        assert_eq!(map.team(yellow).hives.len(), 1);
+
+        assert!(!remove_piece(&mut map, Piece::Hive(yellow, 0), true));
+        assert_eq!(map.team(yellow).hives.len(), 1);
+        assert_eq!(map.team(purple).hives.len(), 1);
+
+        // a team with a spare hive can lose one, but its partner on the other side still stays
+        let spare = HiveLayout {
+            position: Vec2::new(200.0, 0.0),
+            role: HiveRole::Any,
+        };
+        map.team_mut(purple).hives.insert(0, spare);
+        assert!(remove_piece(&mut map, Piece::Hive(purple, 0), true));
+        assert_eq!(map.team(purple).hives.len(), 1);
+        assert_eq!(map.team(yellow).hives.len(), 1);
+    }
+
+    #[test]
+    fn fixed_pieces_cannot_be_removed() {
+        let map = day();
+        let (yellow, _) = teams();
+        for piece in [
+            Piece::BerryCells(yellow),
+            Piece::JoinGate(yellow),
+            Piece::ShipStart,
+            Piece::ShipGoal(Direction::Left),
+        ] {
+            assert!(!piece.can_remove(&map), "{:?}", piece);
+        }
+    }
+
+    #[test]
+    fn team_pieces_mirror_the_team_on_the_other_side() {
+        let map = day();
+        let (yellow, purple) = teams();
+        assert_eq!(
+            Piece::BerryCells(yellow).mirror(&map),
+            Some(Piece::BerryCells(purple))
+        );
+        assert_eq!(
+            Piece::Hive(purple, 0).mirror(&map),
+            Some(Piece::Hive(yellow, 0))
+        );
+        assert_eq!(
+            Piece::ShipGoal(Direction::Left).mirror(&map),
+            Some(Piece::ShipGoal(Direction::Right))
+        );
+        assert_eq!(Piece::ShipStart.mirror(&map), None);
+    }
+}
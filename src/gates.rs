@@ -1,40 +1,91 @@
 use bevy::{prelude::*, sprite::Anchor};
 use bevy_inspector_egui::egui::lerp;
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    berries::Berry,
+    berries::{drop_held_berry, Berry},
+    combat::Role,
+    join::JoinGate,
+    map::Map,
     player::{
-        Player, Queen, Team, Wings, PLAYER_COLLIDER_WIDTH_MULTIPLIER, QUEEN_RECT,
+        body_collider, spawn_combat_boxes, Player, Queen, Speed, Wings, QUEEN_RECT,
         QUEEN_RENDER_HEIGHT, QUEEN_RENDER_WIDTH, WORKER_RENDER_HEIGHT, WORKER_RENDER_WIDTH,
     },
-    GameState, WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_RIGHT_X, WINDOW_WIDTH,
+    rumble::{RumbleEvent, RumbleKind, RumbleTarget},
+    settings::GameSettings,
+    teams::{Team, TEAMS},
+    weapons::Weapon,
+    GameState,
 };
 
 pub struct GatePlugin;
 
-const GATE_WIDTH: f32 = WORKER_RENDER_WIDTH * 1.2;
+pub const GATE_WIDTH: f32 = WORKER_RENDER_WIDTH * 1.2;
 pub const GATE_HEIGHT: f32 = WORKER_RENDER_HEIGHT * 1.5;
 const GATE_TIME: f32 = 1.0;
+const CAPTURE_BAR_HEIGHT: f32 = 6.0;
 
 pub const GATE_NEUTRAL_IDX: usize = 2;
-const GATE_YELLOW_IDX: usize = 0;
-const GATE_PURPLE_IDX: usize = 1;
+/// The neutral frame and one frame for each team
+const GATE_FRAMES: usize = TEAMS.len() + 1;
 
 impl Plugin for GatePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::Join), setup)
-            .add_systems(Update, (check_worker_gate_collisions, progress_gate_timers))
-            .add_systems(OnExit(GameState::GameOver), remove_gates);
+            .add_systems(
+                Update,
+                (
+                    check_worker_gate_collisions,
+                    progress_gate_timers,
+                    add_capture_bars,
+                    capture_gates.after(check_worker_gate_collisions),
+                    show_capture_progress.after(capture_gates),
+                ),
+            )
+            .add_systems(OnExit(GameState::GameOver), remove_gates)
+            .add_systems(OnEnter(GameState::Editor), remove_gates);
     }
 }
 
 #[derive(Component)]
 pub struct Gate;
 
+/// What a worker carrying a berry turns into after standing in the gate.
+/// Warrior gates with a `Weapon` hand out that weapon instead of the one in the settings.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GateKind {
+    Warrior,
+    Speed,
+}
+
+impl GateKind {
+    pub const ALL: [GateKind; 2] = [GateKind::Warrior, GateKind::Speed];
+
+    fn texture(&self) -> &'static str {
+        match self {
+            GateKind::Warrior => "gates.png",
+            GateKind::Speed => "speed-gates.png",
+        }
+    }
+}
+
+/// Queens standing in a gate, and how far the team claiming it has got
+#[derive(Component, Default)]
+struct GateCapture {
+    queens: Vec<Entity>,
+    team: Option<Team>,
+    progress: f32,
+}
+
+#[derive(Component)]
+struct CaptureBar;
+
 #[derive(Bundle)]
 pub struct GateBundle {
     gate: Gate,
+    kind: GateKind,
+    capture: GateCapture,
     sprite_sheet_bundle: SpriteSheetBundle,
     collider: Collider,
     sensor: Sensor,
@@ -44,14 +95,18 @@ impl GateBundle {
     pub fn new(
         x: f32,
         y: f32,
+        kind: GateKind,
         asset_server: &Res<AssetServer>,
         atlases: &mut ResMut<Assets<TextureAtlasLayout>>,
     ) -> Self {
-        let texture: Handle<Image> = asset_server.load("gates.png");
-        let texture_atlas = TextureAtlasLayout::from_grid(Vec2::new(32.0, 32.0), 3, 1, None, None);
+        let texture: Handle<Image> = asset_server.load(kind.texture());
+        let texture_atlas =
+            TextureAtlasLayout::from_grid(Vec2::new(32.0, 32.0), GATE_FRAMES, 1, None, None);
         let atlas_handle = atlases.add(texture_atlas);
         Self {
             gate: Gate,
+            kind,
+            capture: GateCapture::default(),
             sprite_sheet_bundle: SpriteSheetBundle {
                 texture,
                 atlas: TextureAtlas {
@@ -78,50 +133,45 @@ fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut atlases: ResMut<Assets<TextureAtlasLayout>>,
+    map: Res<Map>,
 ) {
-    commands.spawn(GateBundle::new(
-        0.0,
-        WINDOW_BOTTOM_Y + 4.0 * WINDOW_HEIGHT / 9.0 + GATE_HEIGHT / 2.0,
-        &asset_server,
-        &mut atlases,
-    ));
-    commands.spawn(GateBundle::new(
-        WINDOW_RIGHT_X - WINDOW_WIDTH / 3.2,
-        WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0 + GATE_HEIGHT / 2.0,
-        &asset_server,
-        &mut atlases,
-    ));
-    commands.spawn(GateBundle::new(
-        -(WINDOW_RIGHT_X - WINDOW_WIDTH / 3.2),
-        WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0 + GATE_HEIGHT / 2.0,
-        &asset_server,
-        &mut atlases,
-    ));
-    commands.spawn(GateBundle::new(
-        WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0,
-        WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0 + GATE_HEIGHT / 2.0,
-        &asset_server,
-        &mut atlases,
-    ));
-    commands.spawn(GateBundle::new(
-        -(WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0),
-        WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0 + GATE_HEIGHT / 2.0,
-        &asset_server,
-        &mut atlases,
-    ));
+    for gate in &map.gates {
+        let mut entity = commands.spawn(GateBundle::new(
+            gate.position.x,
+            gate.position.y,
+            gate.kind,
+            &asset_server,
+            &mut atlases,
+        ));
+        if let Some(weapon) = gate.weapon {
+            entity.insert(weapon);
+        }
+    }
 }
 
 #[derive(Component)]
 struct GateTimer {
     timer: Timer,
+    kind: GateKind,
+    weapon: Option<Weapon>,
 }
 
 fn check_worker_gate_collisions(
     mut players_with_berries: Query<
-        (Has<GateTimer>, Has<Berry>, Has<Queen>, &Team, &mut Sprite),
+        (
+            Has<GateTimer>,
+            Has<Berry>,
+            Has<Queen>,
+            Has<Speed>,
+            &Team,
+            &mut Sprite,
+        ),
         With<Player>,
     >,
-    mut gates: Query<(Option<&Team>, &mut TextureAtlas), (With<Gate>, Without<Player>)>,
+    mut gates: Query<
+        (Option<&Team>, &GateKind, Option<&Weapon>, &mut GateCapture),
+        (With<Gate>, Without<Player>),
+    >,
     mut collision_events: EventReader<CollisionEvent>,
     mut commands: Commands,
 ) {
@@ -129,25 +179,35 @@ fn check_worker_gate_collisions(
         match collision_event {
             CollisionEvent::Started(entity1, entity2, _) => {
                 for (gate_entity, player_entity) in [(entity1, entity2), (entity2, entity1)] {
-                    if let Ok((maybe_gate_team, mut gate_sprite)) = gates.get_mut(*gate_entity) {
-                        if let Ok((player_has_gate_timer, player_has_berry, is_queen, team, _)) =
-                            players_with_berries.get(*player_entity)
+                    if let Ok((maybe_gate_team, &gate_kind, maybe_weapon, mut capture)) =
+                        gates.get_mut(*gate_entity)
+                    {
+                        if let Ok((
+                            player_has_gate_timer,
+                            player_has_berry,
+                            is_queen,
+                            is_fast,
+                            team,
+                            _,
+                        )) = players_with_berries.get(*player_entity)
                         {
-                            if is_queen {
-                                commands.entity(*gate_entity).insert(*team);
-                                gate_sprite.index = match team {
-                                    Team::Yellow => GATE_YELLOW_IDX,
-                                    Team::Purple => GATE_PURPLE_IDX,
-                                };
+                            if is_queen && !capture.queens.contains(player_entity) {
+                                capture.queens.push(*player_entity);
                             }
                             if let Some(gate_team) = maybe_gate_team {
                                 if gate_team != team {
                                     continue;
                                 }
                             }
+                            // speed does not stack, so fast workers keep their berry
+                            if gate_kind == GateKind::Speed && is_fast {
+                                continue;
+                            }
                             if !player_has_gate_timer && player_has_berry {
                                 commands.entity(*player_entity).insert(GateTimer {
                                     timer: Timer::from_seconds(GATE_TIME, TimerMode::Once),
+                                    kind: gate_kind,
+                                    weapon: maybe_weapon.copied(),
                                 });
                             }
                         }
@@ -156,8 +216,9 @@ fn check_worker_gate_collisions(
             }
             CollisionEvent::Stopped(entity1, entity2, _) => {
                 for (gate_entity, player_entity) in [(entity1, entity2), (entity2, entity1)] {
-                    if gates.get(*gate_entity).is_ok() {
-                        if let Ok((player_has_gate_timer, player_has_berry, _, _, mut sprite)) =
+                    if let Ok((.., mut capture)) = gates.get_mut(*gate_entity) {
+                        capture.queens.retain(|queen| queen != player_entity);
+                        if let Ok((player_has_gate_timer, player_has_berry, _, _, _, mut sprite)) =
                             players_with_berries.get_mut(*player_entity)
                         {
                             if player_has_berry && player_has_gate_timer {
@@ -181,18 +242,36 @@ fn check_worker_gate_collisions(
 fn progress_gate_timers(
     mut commands: Commands,
     mut players_with_gate_timers: Query<(
-        (Entity, &mut Sprite, &mut Transform, &Team),
+        (Entity, &mut Sprite, &mut Transform, &Team, &Player),
         &mut GateTimer,
     )>,
     time: Res<Time>,
     asset_server: Res<AssetServer>,
+    mut ev_rumble: EventWriter<RumbleEvent>,
+    game_settings: Res<GameSettings>,
 ) {
-    for ((entity, mut sprite, mut transform, team), mut gate_timer) in
+    for ((entity, mut sprite, mut transform, team, player), mut gate_timer) in
         players_with_gate_timers.iter_mut()
     {
         gate_timer.timer.tick(time.delta());
 
-        if gate_timer.timer.finished() {
+        if gate_timer.timer.finished() && gate_timer.kind == GateKind::Speed {
+            sprite.custom_size = Some(Vec2 {
+                x: WORKER_RENDER_WIDTH,
+                y: WORKER_RENDER_HEIGHT,
+            });
+            sprite.anchor = Anchor::Center;
+            commands
+                .entity(entity)
+                .remove::<GateTimer>()
+                .remove::<Berry>()
+                .insert(Speed)
+                .add(drop_held_berry);
+            ev_rumble.send(RumbleEvent {
+                target: RumbleTarget::Player(player.player_controller),
+                kind: RumbleKind::BecameSpeedWorker,
+            });
+        } else if gate_timer.timer.finished() {
             let (player_width, player_height) = (QUEEN_RENDER_WIDTH, QUEEN_RENDER_HEIGHT);
             sprite.custom_size = Some(Vec2 {
                 x: player_width,
@@ -206,14 +285,16 @@ fn progress_gate_timers(
                 .remove::<GateTimer>()
                 .remove::<Berry>()
                 .insert(Wings)
-                .insert(Collider::cuboid(
-                    player_width / 2.0 * PLAYER_COLLIDER_WIDTH_MULTIPLIER,
-                    player_height / 2.0,
-                ))
-                .despawn_descendants();
-            commands.entity(entity).insert(match team {
-                Team::Yellow => asset_server.load::<Image>("spritesheets/fighterYellow.png"),
-                Team::Purple => asset_server.load::<Image>("spritesheets/fighterPurple.png"),
+                .insert(gate_timer.weapon.unwrap_or(game_settings.fighter_weapon))
+                .insert(body_collider(true))
+                .despawn_descendants()
+                .with_children(|parent| spawn_combat_boxes(parent, Role::Fighter));
+            commands
+                .entity(entity)
+                .insert(asset_server.load::<Image>(team.definition().fighter_spritesheet));
+            ev_rumble.send(RumbleEvent {
+                target: RumbleTarget::Player(player.player_controller),
+                kind: RumbleKind::BecameFighter,
             });
         } else {
             // grow sprite
@@ -233,8 +314,122 @@ fn progress_gate_timers(
     }
 }
 
+fn add_capture_bars(
+    new_gates: Query<Entity, (Added<Gate>, Without<JoinGate>)>,
+    mut commands: Commands,
+) {
+    for gate in &new_gates {
+        commands.entity(gate).with_children(|children| {
+            children.spawn((
+                CaptureBar,
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::ZERO),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(
+                        0.0,
+                        (GATE_HEIGHT + CAPTURE_BAR_HEIGHT) / 2.0,
+                        1.0,
+                    ),
+                    ..Default::default()
+                },
+            ));
+        });
+    }
+}
+
+/// Queens claim a gate for their team by staying in it until the capture time runs out
+fn capture_gates(
+    mut gates: Query<
+        (
+            Entity,
+            &mut GateCapture,
+            Option<&Team>,
+            &mut TextureAtlas,
+            Has<JoinGate>,
+        ),
+        With<Gate>,
+    >,
+    queens: Query<&Team, With<Queen>>,
+    mut commands: Commands,
+    game_settings: Res<GameSettings>,
+    time: Res<Time>,
+) {
+    let claimed_join_gates: Vec<Team> = gates
+        .iter()
+        .filter(|(.., is_join_gate)| *is_join_gate)
+        .filter_map(|(_, _, maybe_gate_team, ..)| maybe_gate_team.copied())
+        .collect();
+    for (gate, mut capture, maybe_gate_team, mut gate_sprite, is_join_gate) in &mut gates {
+        // queens that died or left no longer hold the gate
+        capture.queens.retain(|&queen| queens.contains(queen));
+        let teams: Vec<Team> = capture
+            .queens
+            .iter()
+            .filter_map(|&queen| queens.get(queen).ok())
+            .copied()
+            .collect();
+        let Some(&team) = teams.first() else {
+            capture.team = None;
+            capture.progress = 0.0;
+            continue;
+        };
+        // an enemy queen in the gate stalls the capture until one of them leaves
+        if teams.iter().any(|&other_team| other_team != team) {
+            continue;
+        }
+        // a team holds one join gate at most, so every team has to claim its own
+        if maybe_gate_team == Some(&team) || (is_join_gate && claimed_join_gates.contains(&team)) {
+            capture.team = None;
+            capture.progress = 0.0;
+            continue;
+        }
+        if capture.team != Some(team) {
+            capture.team = Some(team);
+            capture.progress = 0.0;
+        }
+        capture.progress += time.delta_seconds();
+        // join gates are claimed as soon as a queen flies over them
+        let capture_time = if is_join_gate {
+            0.0
+        } else {
+            game_settings.gate_capture_time
+        };
+        if capture.progress >= capture_time {
+            commands.entity(gate).insert(team);
+            gate_sprite.index = team.definition().gate_index;
+            capture.team = None;
+            capture.progress = 0.0;
+        }
+    }
+}
+
+fn show_capture_progress(
+    gates: Query<(&GateCapture, &Children), With<Gate>>,
+    mut capture_bars: Query<&mut Sprite, With<CaptureBar>>,
+    game_settings: Res<GameSettings>,
+) {
+    for (capture, children) in &gates {
+        for &child in children {
+            let Ok(mut sprite) = capture_bars.get_mut(child) else {
+                continue;
+            };
+            let percent_done = if game_settings.gate_capture_time > 0.0 {
+                (capture.progress / game_settings.gate_capture_time).min(1.0)
+            } else {
+                0.0
+            };
+            sprite.custom_size = Some(Vec2::new(GATE_WIDTH * percent_done, CAPTURE_BAR_HEIGHT));
+            if let Some(team) = capture.team {
+                sprite.color = team.color();
+            }
+        }
+    }
+}
+
 fn remove_gates(gates: Query<Entity, With<Gate>>, mut commands: Commands) {
     for gate in &gates {
-        commands.entity(gate).despawn();
+        commands.entity(gate).despawn_recursive();
     }
 }
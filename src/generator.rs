@@ -0,0 +1,263 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    berries::BERRY_RENDER_RADIUS,
+    gates::{GateKind, GATE_HEIGHT},
+    hive::HiveRole,
+    map::{BerryCellsLayout, GateLayout, HiveLayout, Map, PlatformLayout, ShipLayout, TeamLayout},
+    player::Direction,
+    teams::{Team, TEAMS},
+    validate::validate,
+    WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_LEFT_X, WINDOW_WIDTH,
+};
+
+/// Platforms sit on rows this far apart, the same rows the built-in maps use
+const LAYER_HEIGHT: f32 = WINDOW_HEIGHT / 9.0;
+/// The rows between the floor and the hives that get filled with platforms
+const FIELD_ROWS: RangeInclusive<usize> = 1..=6;
+/// The row the hives stand on, closed off while the lobby is open so queens join up there
+const HIVE_ROW: usize = 7;
+const PLATFORM_THICKNESS: f32 = 20.0;
+const WALL_THICKNESS: f32 = 20.0;
+const JOIN_PLATFORM_THICKNESS: f32 = 5.0;
+const PLATFORM_WIDTHS: [f32; 6] = [64.0, 76.8, 96.0, 128.0, 160.0, 256.0];
+const MIDDLE_PLATFORM_WIDTHS: [f32; 2] = [96.0, 160.0];
+const HIVE_PLATFORM_WIDTH: f32 = 192.0;
+/// Everything is placed in steps of this many pixels
+const STEP: f32 = 16.0;
+/// How many steps apart platforms on a row are
+const GAP_STEPS: RangeInclusive<usize> = 6..=20;
+/// How far from the middle of the screen a ship goal can be, in steps
+const GOAL_STEPS: RangeInclusive<usize> = 52..=57;
+/// How far from the middle of the screen the bunches on the floor can be, in steps
+const FLOOR_BUNCH_STEPS: RangeInclusive<usize> = 10..=50;
+/// How far above the middle of its platform a bunch of berries hangs
+const BUNCH_HEIGHT: f32 = 20.0;
+const BUNCHES_ON_PLATFORMS_PER_SIDE: usize = 3;
+/// Layouts that do not pass `validate` are thrown away, up to this many
+const MAX_ATTEMPTS: usize = 100;
+
+/// Lays out a mirrored map from a seed, the same seed always gives the same map.
+/// Only layouts without a problem `validate` can find for games to `berries_to_win` are kept,
+/// gives up when none turns up.
+pub fn generate(seed: u64, berries_to_win: i32) -> Option<Map> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..MAX_ATTEMPTS)
+        .map(|_| lay_out(&mut rng, format!("Seed {seed}")))
+        .find(|map| validate(map, berries_to_win).is_empty())
+}
+
+fn row_y(row: usize) -> f32 {
+    WINDOW_BOTTOM_Y + row as f32 * LAYER_HEIGHT
+}
+
+/// A spot and its mirror image on the other side, spots in the middle are their own image
+fn both_sides(position: Vec2) -> Vec<Vec2> {
+    if position.x == 0.0 {
+        vec![position]
+    } else {
+        vec![position, Vec2::new(-position.x, position.y)]
+    }
+}
+
+fn add_platform(map: &mut Map, position: Vec2, size: Vec2) {
+    map.platforms.extend(
+        both_sides(position)
+            .into_iter()
+            .map(|position| PlatformLayout { position, size }),
+    );
+}
+
+/// Everything is laid out on the left and mirrored to the right
+fn lay_out(rng: &mut StdRng, name: String) -> Map {
+    let goal = rng.gen_range(GOAL_STEPS) as f32 * STEP;
+    let mut map = Map {
+        name,
+        background: Color::hsl(rng.gen_range(0.0..360.0), 0.5, rng.gen_range(0.2..0.6)).as_rgba(),
+        platforms: Vec::new(),
+        berry_bunches: Vec::new(),
+        gates: Vec::new(),
+        ship: ShipLayout {
+            start: Vec2::new(0.0, row_y(0) + LAYER_HEIGHT / 4.0),
+            left_goal: -goal,
+            right_goal: goal,
+        },
+        join_platforms: Vec::new(),
+        teams: Vec::new(),
+    };
+    // the frame every built-in map shares: floor, ceiling and the walls around the hives
+    let floor = Vec2::new(WINDOW_WIDTH, PLATFORM_THICKNESS);
+    add_platform(&mut map, Vec2::new(0.0, row_y(0)), floor);
+    add_platform(&mut map, Vec2::new(0.0, row_y(9)), floor);
+    add_platform(
+        &mut map,
+        Vec2::new(WINDOW_LEFT_X, row_y(HIVE_ROW)),
+        Vec2::new(WALL_THICKNESS, LAYER_HEIGHT * 4.0),
+    );
+    add_platform(
+        &mut map,
+        Vec2::new(0.0, row_y(HIVE_ROW + 1)),
+        Vec2::new(WALL_THICKNESS, LAYER_HEIGHT * 2.0),
+    );
+
+    lay_out_hives(rng, &mut map);
+
+    // the platforms pieces can go on
+    let mut spots = Vec::new();
+    let mut middle_spots = Vec::new();
+    for row in FIELD_ROWS {
+        let y = row_y(row);
+        let mut right_end = 0.0;
+        if rng.gen_bool(0.5) {
+            let width = *MIDDLE_PLATFORM_WIDTHS.choose(rng).unwrap();
+            add_platform(
+                &mut map,
+                Vec2::new(0.0, y),
+                Vec2::new(width, PLATFORM_THICKNESS),
+            );
+            middle_spots.push(Vec2::new(0.0, y));
+            right_end = -width / 2.0 - *GAP_STEPS.start() as f32 * STEP;
+        }
+        let mut x = WINDOW_LEFT_X + rng.gen_range(0..=*GAP_STEPS.end()) as f32 * STEP;
+        loop {
+            let width = *PLATFORM_WIDTHS.choose(rng).unwrap();
+            if x + width > right_end {
+                break;
+            }
+            let position = Vec2::new(x + width / 2.0, y);
+            add_platform(&mut map, position, Vec2::new(width, PLATFORM_THICKNESS));
+            spots.push(position);
+            x += width + rng.gen_range(GAP_STEPS) as f32 * STEP;
+        }
+    }
+    spots.shuffle(rng);
+    let mut spots = spots.into_iter();
+
+    if let Some(&middle) = middle_spots.choose(rng) {
+        map.gates.push(GateLayout {
+            position: middle + Vec2::new(0.0, GATE_HEIGHT / 2.0),
+            kind: GateKind::Warrior,
+            weapon: None,
+        });
+    }
+    for kind in GateKind::ALL {
+        if let Some(spot) = spots.next() {
+            map.gates.extend(
+                both_sides(spot + Vec2::new(0.0, GATE_HEIGHT / 2.0))
+                    .into_iter()
+                    .map(|position| GateLayout {
+                        position,
+                        kind,
+                        weapon: None,
+                    }),
+            );
+        }
+    }
+    let floor_bunch = Vec2::new(
+        -(rng.gen_range(FLOOR_BUNCH_STEPS) as f32) * STEP,
+        row_y(0) + BUNCH_HEIGHT,
+    );
+    map.berry_bunches.extend(both_sides(floor_bunch));
+    for spot in spots.take(BUNCHES_ON_PLATFORMS_PER_SIDE) {
+        map.berry_bunches
+            .extend(both_sides(spot + Vec2::new(0.0, BUNCH_HEIGHT)));
+    }
+    map
+}
+
+/// Splits the hive row on each side between the teams playing there, from the middle out.
+/// Every team gets a hive on a platform with its berry cells beside it, and a join gate on
+/// the platforms that close off the rest of its stretch while the lobby is open.
+fn lay_out_hives(rng: &mut StdRng, map: &mut Map) {
+    let teams: Vec<Team> = Team::all(TEAMS.len()).collect();
+    let left_teams: Vec<Team> = teams
+        .iter()
+        .copied()
+        .filter(|team| team.side() == Direction::Left)
+        .collect();
+    let stretch = -WINDOW_LEFT_X / left_teams.len() as f32;
+    let y = row_y(HIVE_ROW);
+    let mut layouts = vec![None; teams.len()];
+    for (spot, &team) in left_teams.iter().enumerate() {
+        let right = -(spot as f32) * stretch;
+        let left = right - stretch;
+        let room = ((stretch - HIVE_PLATFORM_WIDTH) / STEP) as usize;
+        let hive_x = left + HIVE_PLATFORM_WIDTH / 2.0 + rng.gen_range(0..=room) as f32 * STEP;
+        let (platform_left, platform_right) = (
+            hive_x - HIVE_PLATFORM_WIDTH / 2.0,
+            hive_x + HIVE_PLATFORM_WIDTH / 2.0,
+        );
+        add_platform(
+            map,
+            Vec2::new(hive_x, y),
+            Vec2::new(HIVE_PLATFORM_WIDTH, PLATFORM_THICKNESS),
+        );
+        // the join gate goes on the wider of the stretches beside the hive platform
+        let mut join_gate_x = 0.0;
+        let mut widest = 0.0;
+        for (from, to) in [(left, platform_left), (platform_right, right)] {
+            if to - from <= 0.0 {
+                continue;
+            }
+            map.join_platforms
+                .extend(
+                    both_sides(Vec2::new((from + to) / 2.0, y))
+                        .into_iter()
+                        .map(|position| PlatformLayout {
+                            position,
+                            size: Vec2::new(to - from, JOIN_PLATFORM_THICKNESS),
+                        }),
+                );
+            if to - from > widest {
+                widest = to - from;
+                join_gate_x = (from + to) / 2.0;
+            }
+        }
+
+        let hive = Vec2::new(hive_x, row_y(HIVE_ROW + 1));
+        // two cells towards the middle, where the built-in maps put them
+        let cells = hive + Vec2::new(BERRY_RENDER_RADIUS * 4.2, -LAYER_HEIGHT / 5.0);
+        let join_gate = Vec2::new(join_gate_x, y + GATE_HEIGHT / 2.0);
+        let mirrored = team.mirrored();
+        for (team, mirror) in [(Some(team), 1.0), (mirrored, -1.0)] {
+            let Some(team) = team else {
+                continue;
+            };
+            let flip = |position: Vec2| Vec2::new(position.x * mirror, position.y);
+            layouts[team.index()] = Some(TeamLayout {
+                hives: vec![HiveLayout {
+                    position: flip(hive),
+                    role: HiveRole::Any,
+                }],
+                berry_cells: BerryCellsLayout {
+                    position: flip(cells),
+                    rows: 3,
+                },
+                join_gate: flip(join_gate),
+            });
+        }
+    }
+    map.teams = layouts
+        .into_iter()
+        .map(|layout| layout.expect("every team has a side"))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::GameSettings;
+
+    #[test]
+    fn seeds_give_the_same_playable_map_every_time() {
+        let berries_to_win = GameSettings::default().berries_to_win;
+        for seed in 0..3 {
+            let map = generate(seed, berries_to_win).expect("the seed gives a playable map");
+            assert_eq!(map.teams.len(), TEAMS.len());
+            assert_eq!(generate(seed, berries_to_win), Some(map));
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    map::Map,
+    player::{
+        add_delayed_player_spawners, DelayedPlayerSpawner, Direction, KnockBackEvent, Player,
+        WORKER_RENDER_HEIGHT, WORKER_RENDER_WIDTH,
+    },
+    settings::GameSettings,
+    teams::Team,
+    WINDOW_HEIGHT, WINDOW_TOP_Y,
+};
+
+pub const HIVE_RADIUS: f32 = 70.0;
+const EGG_WIDTH: f32 = WORKER_RENDER_WIDTH * 0.6;
+const EGG_HEIGHT: f32 = WORKER_RENDER_HEIGHT * 0.8;
+const EGG_WOBBLE_SPEED: f32 = 25.0;
+const EGG_WOBBLE_ANGLE: f32 = 0.3;
+
+pub struct HivePlugin;
+
+impl Plugin for HivePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                // players reset after a game hatch at the hives of the new map
+                place_hives.before(add_delayed_player_spawners),
+                lay_eggs,
+                hatch_eggs,
+                guard_hives,
+            ),
+        );
+    }
+}
+
+/// Which players respawn at a hive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HiveRole {
+    Any,
+    Queens,
+    Workers,
+}
+
+impl HiveRole {
+    pub const ALL: [HiveRole; 3] = [HiveRole::Any, HiveRole::Queens, HiveRole::Workers];
+}
+
+/// Where players of a team respawn, hatching from an egg
+#[derive(Component)]
+pub struct Hive {
+    pub team: Team,
+    pub role: HiveRole,
+}
+
+impl Hive {
+    fn accepts(&self, team: Team, is_queen: bool) -> bool {
+        self.team == team
+            && match self.role {
+                HiveRole::Any => true,
+                HiveRole::Queens => is_queen,
+                HiveRole::Workers => !is_queen,
+            }
+    }
+}
+
+#[derive(Bundle)]
+pub struct HiveBundle {
+    hive: Hive,
+    sprite_bundle: SpriteBundle,
+    collider: Collider,
+    sensor: Sensor,
+}
+
+impl HiveBundle {
+    pub fn new(
+        x: f32,
+        y: f32,
+        team: Team,
+        role: HiveRole,
+        asset_server: &Res<AssetServer>,
+    ) -> Self {
+        Self {
+            hive: Hive { team, role },
+            sprite_bundle: SpriteBundle {
+                texture: asset_server.load("hive.png"),
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(HIVE_RADIUS * 2.0, HIVE_RADIUS * 1.75)),
+                    color: team.color(),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(x, y, -12.0),
+                ..Default::default()
+            },
+            collider: Collider::ball(HIVE_RADIUS),
+            sensor: Sensor,
+        }
+    }
+}
+
+/// Puts up the hives of every team in play, again whenever the number of teams or the map
+/// changes
+fn place_hives(
+    hives: Query<Entity, With<Hive>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_settings: Res<GameSettings>,
+    map: Res<Map>,
+    mut placed_for: Local<usize>,
+) {
+    if *placed_for == game_settings.team_count && !map.is_changed() {
+        return;
+    }
+    *placed_for = game_settings.team_count;
+    for hive in &hives {
+        commands.entity(hive).despawn();
+    }
+    for team in game_settings.teams() {
+        for hive in &map.team(team).hives {
+            commands.spawn(HiveBundle::new(
+                hive.position.x,
+                hive.position.y,
+                team,
+                hive.role,
+                &asset_server,
+            ));
+        }
+    }
+}
+
+/// Picks one of the hives a player can respawn at, or the top of the map if there are none
+pub fn spawn_point<'a>(
+    hives: impl Iterator<Item = (&'a Hive, &'a Transform)>,
+    team: Team,
+    is_queen: bool,
+) -> Vec2 {
+    hives
+        .filter(|(hive, _)| hive.accepts(team, is_queen))
+        .map(|(_, transform)| transform.translation.truncate())
+        .choose(&mut rand::thread_rng())
+        .unwrap_or(Vec2::new(0.0, WINDOW_TOP_Y - (WINDOW_HEIGHT / 9.0)))
+}
+
+/// Players waiting to respawn show up as an egg in the hive they will hatch from
+fn lay_eggs(
+    spawners: Query<(Entity, &DelayedPlayerSpawner), Added<DelayedPlayerSpawner>>,
+    hives: Query<(&Hive, &Transform)>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, spawner) in &spawners {
+        let ev = spawner.event;
+        // players joining or swapping teams appear right away
+        if ev.delay <= 0.0 {
+            continue;
+        }
+        let point = spawn_point(hives.iter(), ev.team, ev.is_queen);
+        commands.entity(entity).insert(SpriteBundle {
+            texture: asset_server.load("egg.png"),
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(EGG_WIDTH, EGG_HEIGHT)),
+                color: ev.team.color(),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(point.extend(1.0)),
+            ..Default::default()
+        });
+    }
+}
+
+/// The egg of a player waiting to respawn wobbles harder the closer it is to hatching
+fn hatch_eggs(
+    mut spawners: Query<(&DelayedPlayerSpawner, &mut Transform, &mut Sprite)>,
+    hives: Query<(&Hive, &Transform), Without<DelayedPlayerSpawner>>,
+    time: Res<Time>,
+) {
+    for (spawner, mut transform, mut sprite) in &mut spawners {
+        let ev = spawner.event;
+        sprite.color = ev.team.color();
+        // the player swapped teams while waiting, so the egg moves to their new hive
+        let in_hive = hives.iter().any(|(hive, hive_transform)| {
+            hive.accepts(ev.team, ev.is_queen)
+                && hive_transform.translation.truncate() == transform.translation.truncate()
+        });
+        if !in_hive && !hives.is_empty() {
+            let point = spawn_point(hives.iter(), ev.team, ev.is_queen);
+            transform.translation = point.extend(transform.translation.z);
+        }
+        let wobble = (time.elapsed_seconds() * EGG_WOBBLE_SPEED).sin();
+        transform.rotation = Quat::from_rotation_z(wobble * EGG_WOBBLE_ANGLE * spawner.progress());
+    }
+}
+
+/// Knocks enemies out of a hive, so nobody can camp where the other team hatches
+fn guard_hives(
+    mut collision_events: EventReader<CollisionEvent>,
+    hives: Query<(&Hive, &Transform)>,
+    players: Query<(&Team, &Transform), With<Player>>,
+    mut ev_knockback: EventWriter<KnockBackEvent>,
+    game_settings: Res<GameSettings>,
+) {
+    for collision_event in collision_events.read() {
+        if !game_settings.hive_barrier {
+            continue;
+        }
+        if let CollisionEvent::Started(entity1, entity2, _flags) = collision_event {
+            for (hive_entity, player_entity) in [(entity1, entity2), (entity2, entity1)] {
+                let (Ok((hive, hive_transform)), Ok((&team, transform))) =
+                    (hives.get(*hive_entity), players.get(*player_entity))
+                else {
+                    continue;
+                };
+                if hive.team == team {
+                    continue;
+                }
+                ev_knockback.send(KnockBackEvent {
+                    entity: *player_entity,
+                    direction: if transform.translation.x < hive_transform.translation.x {
+                        Direction::Left
+                    } else {
+                        Direction::Right
+                    },
+                });
+            }
+        }
+    }
+}
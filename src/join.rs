@@ -4,11 +4,20 @@ use leafwing_input_manager::action_state::ActionState;
 
 use crate::{
     berries::{Berry, BerryBundle},
-    gates::{GateBundle, GATE_HEIGHT, GATE_NEUTRAL_IDX},
-    platforms::{PlatformBundle, PLATFORM_HEIGHT},
-    player::{Action, Player, PlayerController, Queen, SpawnPlayerEvent, Team},
+    bot::{hand_over_to_bot, BotIds},
+    controls::ControlsConfig,
+    gates::{GateBundle, GateKind, GATE_NEUTRAL_IDX},
+    keyboard::KeyboardLayout,
+    lobby::{Ready, SwapTeamEvent},
+    map::Map,
+    platforms::PlatformBundle,
+    player::{
+        Action, DelayedPlayerSpawner, Direction, Player, PlayerController, Queen, SpawnPlayerEvent,
+    },
+    settings::GameSettings,
     ship::RidingOnShip,
-    GameState, WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_RIGHT_X, WINDOW_WIDTH,
+    teams::Team,
+    GameState,
 };
 
 const TEMP_PLATFORM_COLOR: Color = Color::BLACK;
@@ -17,14 +26,22 @@ pub struct JoinPlugin;
 #[derive(Resource, Default)]
 pub struct JoinedGamepads(pub HashSet<Gamepad>);
 
+#[derive(Resource, Default)]
+pub struct JoinedKeyboards(pub HashSet<KeyboardLayout>);
+
 impl Plugin for JoinPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<JoinedGamepads>()
+            .init_resource::<JoinedKeyboards>()
             .add_systems(
                 Update,
                 (
-                    (check_for_start_game, disconnect).run_if(in_state(GameState::Join)),
+                    (place_join_gates, check_for_start_game)
+                        .chain()
+                        .run_if(in_state(GameState::Join)),
+                    disconnect,
                     join,
+                    join_keyboards,
                 ),
             )
             .add_systems(OnEnter(GameState::Join), setup_join)
@@ -35,8 +52,22 @@ impl Plugin for JoinPlugin {
 fn check_for_start_game(
     mut next_state: ResMut<NextState<GameState>>,
     join_gates: Query<Has<Team>, With<JoinGate>>,
+    players: Query<(&Player, &Team, Has<Queen>, Has<Ready>)>,
+    game_settings: Res<GameSettings>,
 ) {
-    if join_gates.iter().all(|x| x) {
+    // bots are always ready
+    let mut humans = players
+        .iter()
+        .filter(|(player, ..)| !player.player_controller.is_bot())
+        .peekable();
+    let everyone_ready = humans.peek().is_some() && humans.all(|(.., ready)| ready);
+    let teams_have_queens = game_settings.teams().all(|team| {
+        players
+            .iter()
+            .any(|(_, &queen_team, is_queen, _)| queen_team == team && is_queen)
+    });
+    let join_gates_claimed = !join_gates.is_empty() && join_gates.iter().all(|x| x);
+    if join_gates_claimed || (everyone_ready && teams_have_queens) {
         next_state.set(GameState::Play);
     }
 }
@@ -44,59 +75,55 @@ fn check_for_start_game(
 #[derive(Component)]
 pub struct TempPlatform;
 
+/// A gate that starts the game once every team's queen has flown over one
 #[derive(Component)]
-pub struct JoinGate;
+pub struct JoinGate {
+    /// The team the gate was put out for, any queen can claim it but bots go for their own
+    pub home: Team,
+}
 
-fn setup_join(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut atlases: ResMut<Assets<TextureAtlasLayout>>,
-) {
-    for sign in [-1.0, 1.0] {
-        commands.spawn((
-            PlatformBundle::new(
-                sign * (WINDOW_RIGHT_X - WINDOW_WIDTH / 40.0 - WINDOW_WIDTH / 10.0
-                    + WINDOW_WIDTH / 60.0),
-                WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0,
-                Vec3::new(
-                    (WINDOW_RIGHT_X - WINDOW_WIDTH / 20.0)
-                        - (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0 + WINDOW_WIDTH / 30.0),
-                    PLATFORM_HEIGHT / 4.0,
-                    1.0,
-                ),
-                true,
-                Some(TEMP_PLATFORM_COLOR),
-                &asset_server,
-            ),
-            TempPlatform,
-        ));
+fn setup_join(mut commands: Commands, asset_server: Res<AssetServer>, map: Res<Map>) {
+    for platform in &map.join_platforms {
         commands.spawn((
             PlatformBundle::new(
-                sign * (((WINDOW_WIDTH / 10.0)
-                    + (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0 - WINDOW_WIDTH / 30.0))
-                    / 2.0),
-                WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0,
-                Vec3::new(
-                    (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0 - WINDOW_WIDTH / 30.0)
-                        - WINDOW_WIDTH / 10.0,
-                    PLATFORM_HEIGHT / 4.0,
-                    1.0,
-                ),
-                true,
+                platform.position.x,
+                platform.position.y,
+                platform.size.extend(1.0),
+                platform.is_floor(),
                 Some(TEMP_PLATFORM_COLOR),
                 &asset_server,
             ),
             TempPlatform,
         ));
+    }
+}
 
+/// Puts out a join gate for every team in play, again whenever the number of teams changes
+fn place_join_gates(
+    join_gates: Query<Entity, With<JoinGate>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlasLayout>>,
+    game_settings: Res<GameSettings>,
+    map: Res<Map>,
+) {
+    if join_gates.iter().count() == game_settings.team_count {
+        return;
+    }
+    for join_gate in &join_gates {
+        commands.entity(join_gate).despawn();
+    }
+    for team in game_settings.teams() {
+        let spot = map.team(team).join_gate;
         commands.spawn((
             GateBundle::new(
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 3.2) * sign,
-                WINDOW_BOTTOM_Y + 8.0 * WINDOW_HEIGHT / 9.0 + GATE_HEIGHT / 2.0,
+                spot.x,
+                spot.y,
+                GateKind::Warrior,
                 &asset_server,
                 &mut atlases,
             ),
-            JoinGate,
+            JoinGate { home: team },
         ));
     }
 }
@@ -114,12 +141,16 @@ fn delete_temp_platforms(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn join(
     mut joined_gamepads: ResMut<JoinedGamepads>,
     gamepads: Res<Gamepads>,
     button_inputs: Res<ButtonInput<GamepadButton>>,
-    queens: Query<&Team, With<Queen>>,
+    players: Query<(&Player, &Team)>,
+    delayed_player_spawners: Query<(Entity, &mut DelayedPlayerSpawner)>,
     mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
+    mut ev_swap_team: EventWriter<SwapTeamEvent>,
+    game_settings: Res<GameSettings>,
 ) {
     for gamepad in gamepads.iter() {
         // Join the game when both bumpers (L+R) on the controller are pressed
@@ -128,32 +159,118 @@ fn join(
             GamepadButton::new(gamepad, GamepadButtonType::LeftTrigger),
             GamepadButton::new(gamepad, GamepadButtonType::RightTrigger),
         ]) {
-            let team = if button_inputs
+            let side = if button_inputs
                 .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::LeftTrigger))
             {
-                Team::Yellow
+                Direction::Left
             } else {
-                Team::Purple
+                Direction::Right
             };
-            let is_queen = !queens.iter().any(|&queen_team| queen_team == team);
-
-            // Make sure a player cannot join twice
-            if !joined_gamepads.0.contains(&gamepad) {
-                ev_spawn_players.send(SpawnPlayerEvent {
-                    team,
-                    is_queen,
-                    player_controller: PlayerController::Gamepad(gamepad),
-                    delay: 0.0,
-                    start_invincible: false,
-                });
-                // Insert the created player and its gamepad to the hashmap of joined players
-                // Since uniqueness was already checked above, we can insert here unchecked
-                joined_gamepads.0.insert(gamepad);
-            }
+            let player_controller = PlayerController::Gamepad(gamepad);
+            let team = join_side(
+                side,
+                player_controller,
+                &players,
+                &delayed_player_spawners,
+                &game_settings,
+            );
+            join_team(
+                player_controller,
+                team,
+                // Make sure a player cannot join twice
+                !joined_gamepads.0.insert(gamepad),
+                &mut ev_spawn_players,
+                &mut ev_swap_team,
+            );
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+fn join_keyboards(
+    mut joined_keyboards: ResMut<JoinedKeyboards>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    controls: Res<ControlsConfig>,
+    players: Query<(&Player, &Team)>,
+    delayed_player_spawners: Query<(Entity, &mut DelayedPlayerSpawner)>,
+    mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
+    mut ev_swap_team: EventWriter<SwapTeamEvent>,
+    game_settings: Res<GameSettings>,
+) {
+    for layout in KeyboardLayout::ALL {
+        let keyboard_controls = controls.keyboard(layout);
+        let side = if keyboard_input.just_pressed(keyboard_controls.join_yellow) {
+            Direction::Left
+        } else if keyboard_input.just_pressed(keyboard_controls.join_purple) {
+            Direction::Right
+        } else {
+            continue;
+        };
+        let player_controller = PlayerController::Keyboard { layout };
+        let team = join_side(
+            side,
+            player_controller,
+            &players,
+            &delayed_player_spawners,
+            &game_settings,
+        );
+        join_team(
+            player_controller,
+            team,
+            // Make sure a layout cannot join twice
+            !joined_keyboards.0.insert(layout),
+            &mut ev_spawn_players,
+            &mut ev_swap_team,
+        );
+    }
+}
+
+/// The team a controller's join button for `side` picks, pressing it again goes round the
+/// teams on that side
+fn join_side(
+    side: Direction,
+    player_controller: PlayerController,
+    players: &Query<(&Player, &Team)>,
+    delayed_player_spawners: &Query<(Entity, &mut DelayedPlayerSpawner)>,
+    game_settings: &GameSettings,
+) -> Team {
+    let current = joined_team(
+        player_controller,
+        players
+            .iter()
+            .map(|(player, &team)| (player.player_controller, team)),
+        delayed_player_spawners,
+    );
+    Team::next_on_side(side, current, game_settings.team_count)
+        .or(Team::next_on_side(side, None, game_settings.team_count))
+        .expect("every side has a team")
+}
+
+/// Joins a new player as a worker, or moves a joined player over to the team
+pub fn join_team(
+    player_controller: PlayerController,
+    team: Team,
+    already_joined: bool,
+    ev_spawn_players: &mut EventWriter<SpawnPlayerEvent>,
+    ev_swap_team: &mut EventWriter<SwapTeamEvent>,
+) {
+    if already_joined {
+        ev_swap_team.send(SwapTeamEvent {
+            player_controller,
+            team,
+        });
+    } else {
+        ev_spawn_players.send(SpawnPlayerEvent {
+            team,
+            is_queen: false,
+            player_controller,
+            delay: 0.0,
+            start_invincible: false,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn disconnect(
     mut commands: Commands,
     action_query: Query<(
@@ -167,8 +284,11 @@ fn disconnect(
         Has<Queen>,
     )>,
     mut joined_gamepads: ResMut<JoinedGamepads>,
+    mut joined_keyboards: ResMut<JoinedKeyboards>,
     asset_server: Res<AssetServer>,
     mut join_gates: Query<(Entity, &Team, &mut TextureAtlas), With<JoinGate>>,
+    state: Res<State<GameState>>,
+    mut bot_ids: ResMut<BotIds>,
 ) {
     for (
         player_entity,
@@ -182,8 +302,21 @@ fn disconnect(
     ) in action_query.iter()
     {
         if action_state.pressed(&Action::Disconnect) {
-            if let PlayerController::Gamepad(gamepad) = player.player_controller {
-                joined_gamepads.0.remove(&gamepad);
+            match player.player_controller {
+                PlayerController::Gamepad(gamepad) => {
+                    joined_gamepads.0.remove(&gamepad);
+                }
+                PlayerController::Keyboard { layout } => {
+                    joined_keyboards.0.remove(&layout);
+                }
+                PlayerController::Midi { .. }
+                | PlayerController::Touch { .. }
+                | PlayerController::Bot { .. } => (),
+            }
+            // a game in progress keeps its teams whole
+            if *state.get() != GameState::Join {
+                hand_over_to_bot(&mut commands, player_entity, player, &mut bot_ids);
+                continue;
             }
             remove_player(
                 &mut commands,
@@ -194,17 +327,60 @@ fn disconnect(
                 maybe_riding_on_ship,
             );
             if is_queen {
-                for (join_gate, join_gate_team, mut gate_sprite) in join_gates.iter_mut() {
-                    if join_gate_team == team {
-                        commands.entity(join_gate).remove::<Team>();
-                        gate_sprite.index = GATE_NEUTRAL_IDX;
-                    }
-                }
+                release_join_gate(&mut commands, &mut join_gates, *team);
             }
         }
     }
 }
 
+/// Leaves for a player who is waiting to respawn, as they have no action state to press
+pub fn cancel_delayed_spawn(
+    commands: &mut Commands,
+    delayed_player_spawners: &mut Query<(Entity, &mut DelayedPlayerSpawner)>,
+    player_controller: PlayerController,
+    state: &GameState,
+    bot_ids: &mut BotIds,
+) {
+    for (entity, mut delayed_player_spawner) in delayed_player_spawners.iter_mut() {
+        if delayed_player_spawner.event.player_controller == player_controller {
+            if *state == GameState::Join {
+                commands.entity(entity).despawn();
+            } else {
+                delayed_player_spawner.event.player_controller = bot_ids.next();
+            }
+        }
+    }
+}
+
+/// The team of a joined player, even while they wait to respawn
+pub fn joined_team(
+    player_controller: PlayerController,
+    mut players: impl Iterator<Item = (PlayerController, Team)>,
+    delayed_player_spawners: &Query<(Entity, &mut DelayedPlayerSpawner)>,
+) -> Option<Team> {
+    players
+        .find(|&(controller, _)| controller == player_controller)
+        .map(|(_, team)| team)
+        .or(delayed_player_spawners
+            .iter()
+            .find(|(_, spawner)| spawner.event.player_controller == player_controller)
+            .map(|(_, spawner)| spawner.event.team))
+}
+
+/// Unclaims the join gate of a team whose queen left
+pub fn release_join_gate(
+    commands: &mut Commands,
+    join_gates: &mut Query<(Entity, &Team, &mut TextureAtlas), With<JoinGate>>,
+    team: Team,
+) {
+    for (join_gate, &join_gate_team, mut gate_sprite) in join_gates.iter_mut() {
+        if join_gate_team == team {
+            commands.entity(join_gate).remove::<Team>();
+            gate_sprite.index = GATE_NEUTRAL_IDX;
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn remove_player(
     commands: &mut Commands,
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::player::Action;
+
+/// A region of the keyboard that one player can use as a controller.
+/// None of the layouts share keys, so up to four players can share a keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum KeyboardLayout {
+    Wasd,
+    Arrows,
+    Ijkl,
+    Numpad,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyboardControls {
+    pub move_left: KeyCode,
+    pub move_right: KeyCode,
+    /// Joins the teams on the left side, pressing it again goes round them
+    pub join_yellow: KeyCode,
+    /// Joins the teams on the right side, pressing it again goes round them
+    pub join_purple: KeyCode,
+    /// Keys for every button-like action
+    pub keys: BTreeMap<Action, KeyCode>,
+}
+
+impl KeyboardLayout {
+    pub const ALL: [KeyboardLayout; 4] = [
+        KeyboardLayout::Wasd,
+        KeyboardLayout::Arrows,
+        KeyboardLayout::Ijkl,
+        KeyboardLayout::Numpad,
+    ];
+
+    pub fn default_controls(&self) -> KeyboardControls {
+        match self {
+            KeyboardLayout::Wasd => KeyboardControls {
+                move_left: KeyCode::KeyA,
+                move_right: KeyCode::KeyD,
+                join_yellow: KeyCode::KeyQ,
+                join_purple: KeyCode::KeyE,
+                keys: BTreeMap::from([
+                    (Action::Jump, KeyCode::Space),
+                    (Action::Dive, KeyCode::KeyS),
+                    (Action::Disconnect, KeyCode::KeyX),
+                    (Action::Ready, KeyCode::KeyR),
+                    (Action::ToggleQueen, KeyCode::KeyW),
+                    (Action::Attack, KeyCode::KeyF),
+                    (Action::Throw, KeyCode::KeyF),
+                ]),
+            },
+            KeyboardLayout::Arrows => KeyboardControls {
+                move_left: KeyCode::ArrowLeft,
+                move_right: KeyCode::ArrowRight,
+                join_yellow: KeyCode::Period,
+                join_purple: KeyCode::Slash,
+                keys: BTreeMap::from([
+                    (Action::Jump, KeyCode::ShiftRight),
+                    (Action::Dive, KeyCode::ArrowDown),
+                    (Action::Disconnect, KeyCode::Backspace),
+                    (Action::Ready, KeyCode::Backslash),
+                    (Action::ToggleQueen, KeyCode::ArrowUp),
+                    (Action::Attack, KeyCode::ControlRight),
+                    (Action::Throw, KeyCode::ControlRight),
+                ]),
+            },
+            KeyboardLayout::Ijkl => KeyboardControls {
+                move_left: KeyCode::KeyJ,
+                move_right: KeyCode::KeyL,
+                join_yellow: KeyCode::KeyU,
+                join_purple: KeyCode::KeyO,
+                keys: BTreeMap::from([
+                    (Action::Jump, KeyCode::Enter),
+                    (Action::Dive, KeyCode::KeyK),
+                    (Action::Disconnect, KeyCode::KeyP),
+                    (Action::Ready, KeyCode::KeyY),
+                    (Action::ToggleQueen, KeyCode::KeyI),
+                    (Action::Attack, KeyCode::KeyH),
+                    (Action::Throw, KeyCode::KeyH),
+                ]),
+            },
+            KeyboardLayout::Numpad => KeyboardControls {
+                move_left: KeyCode::Numpad4,
+                move_right: KeyCode::Numpad6,
+                join_yellow: KeyCode::Numpad7,
+                join_purple: KeyCode::Numpad9,
+                keys: BTreeMap::from([
+                    (Action::Jump, KeyCode::Numpad0),
+                    (Action::Dive, KeyCode::Numpad5),
+                    (Action::Disconnect, KeyCode::NumpadSubtract),
+                    (Action::Ready, KeyCode::NumpadAdd),
+                    (Action::ToggleQueen, KeyCode::Numpad8),
+                    (Action::Attack, KeyCode::NumpadDecimal),
+                    (Action::Throw, KeyCode::NumpadDecimal),
+                ]),
+            },
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use bevy::{
+    ecs::{query::ROQueryItem, system::SystemParam},
+    prelude::*,
+};
+use leafwing_input_manager::action_state::ActionState;
+use rand::seq::SliceRandom;
+
+use crate::{
+    berries::Berry,
+    join::{release_join_gate, remove_player, JoinGate},
+    player::{Action, DelayedPlayerSpawner, Player, PlayerController, Queen, SpawnPlayerEvent},
+    queue::{humans_on_team, Waiting},
+    settings::GameSettings,
+    ship::RidingOnShip,
+    teams::Team,
+    GameState,
+};
+
+const LABEL_FONT_SIZE: f32 = 20.0;
+const READY_LABEL_Y: f32 = 45.0;
+const QUEEN_REQUEST_LABEL_Y: f32 = 65.0;
+
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SwapTeamEvent>()
+            .add_event::<ShuffleTeamsEvent>()
+            .add_systems(
+                Update,
+                (
+                    regroup_teams,
+                    toggle_ready,
+                    toggle_queen,
+                    swap_teams,
+                    shuffle_teams,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Join)),
+            )
+            .add_systems(OnExit(GameState::Join), clear_lobby);
+    }
+}
+
+/// Sent when a joined player asks to play for the other team
+#[derive(Event)]
+pub struct SwapTeamEvent {
+    pub player_controller: PlayerController,
+    pub team: Team,
+}
+
+#[derive(Event)]
+pub struct ShuffleTeamsEvent;
+
+/// A player who is ready for the game to start
+#[derive(Component)]
+pub struct Ready {
+    label: Entity,
+}
+
+/// A worker waiting to take over as queen once the current queen gives it up
+#[derive(Component)]
+struct QueenRequest {
+    label: Entity,
+    requested_at: f32,
+}
+
+type LobbyPlayer = (
+    Entity,
+    &'static Player,
+    &'static Team,
+    Has<Queen>,
+    Has<Berry>,
+    &'static Transform,
+    Option<&'static RidingOnShip>,
+    Option<&'static QueenRequest>,
+);
+
+#[derive(SystemParam)]
+struct LobbyCommands<'w, 's> {
+    commands: Commands<'w, 's>,
+    ev_spawn_players: EventWriter<'w, SpawnPlayerEvent>,
+    join_gates: Query<'w, 's, (Entity, &'static Team, &'static mut TextureAtlas), With<JoinGate>>,
+    asset_server: Res<'w, AssetServer>,
+}
+
+impl LobbyCommands<'_, '_> {
+    /// Spawns the player again with a new team or role
+    fn respawn(&mut self, player: &ROQueryItem<'_, LobbyPlayer>, team: Team, is_queen: bool) {
+        let (entity, player, &old_team, was_queen, has_berry, transform, maybe_riding_on_ship, _) =
+            *player;
+        remove_player(
+            &mut self.commands,
+            entity,
+            has_berry,
+            transform,
+            &self.asset_server,
+            maybe_riding_on_ship,
+        );
+        if was_queen {
+            release_join_gate(&mut self.commands, &mut self.join_gates, old_team);
+        }
+        self.ev_spawn_players.send(SpawnPlayerEvent {
+            team,
+            is_queen,
+            player_controller: player.player_controller,
+            delay: 0.0,
+            start_invincible: false,
+        });
+    }
+
+    fn spawn_label(&mut self, player: Entity, text: &str, y: f32) -> Entity {
+        let label = self
+            .commands
+            .spawn(Text2dBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font: self.asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: LABEL_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ),
+                transform: Transform::from_xyz(0.0, y, 1.0),
+                ..Default::default()
+            })
+            .id();
+        self.commands.entity(player).add_child(label);
+        label
+    }
+
+    /// Hands the queen role of a team to the worker who asked for it first
+    fn crown_next_queen(&mut self, team: Team, players: &Query<LobbyPlayer>, old_queen: Entity) {
+        let next_queen = players
+            .iter()
+            .filter(|&(entity, _, &player_team, ..)| player_team == team && entity != old_queen)
+            .filter_map(|player| player.7.map(|request| (request.requested_at, player)))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+        if let Some((_, next_queen)) = next_queen {
+            self.respawn(&next_queen, team, true);
+        }
+    }
+}
+
+/// Moves players off teams that are no longer in play, onto the first team on the same side
+fn regroup_teams(
+    players: Query<LobbyPlayer>,
+    mut delayed_player_spawners: Query<&mut DelayedPlayerSpawner>,
+    mut lobby: LobbyCommands,
+    game_settings: Res<GameSettings>,
+) {
+    let regrouped_team = |team: Team| {
+        if game_settings.teams().any(|other_team| other_team == team) {
+            None
+        } else {
+            Team::next_on_side(team.side(), None, game_settings.team_count)
+        }
+    };
+    for player in &players {
+        if let Some(team) = regrouped_team(*player.2) {
+            lobby.respawn(&player, team, false);
+        }
+    }
+    for mut delayed_player_spawner in &mut delayed_player_spawners {
+        if let Some(team) = regrouped_team(delayed_player_spawner.event.team) {
+            delayed_player_spawner.event.team = team;
+            delayed_player_spawner.event.is_queen = false;
+        }
+    }
+}
+
+fn toggle_ready(
+    players: Query<(Entity, &ActionState<Action>, &Player, Option<&Ready>)>,
+    mut lobby: LobbyCommands,
+) {
+    for (entity, action_state, player, ready) in &players {
+        if player.player_controller.is_bot() || !action_state.just_pressed(&Action::Ready) {
+            continue;
+        }
+        match ready {
+            Some(ready) => {
+                lobby.commands.entity(ready.label).despawn_recursive();
+                lobby.commands.entity(entity).remove::<Ready>();
+            }
+            None => {
+                let label = lobby.spawn_label(entity, "ready", READY_LABEL_Y);
+                lobby.commands.entity(entity).insert(Ready { label });
+            }
+        }
+    }
+}
+
+fn toggle_queen(
+    players: Query<LobbyPlayer>,
+    action_states: Query<&ActionState<Action>>,
+    mut lobby: LobbyCommands,
+    time: Res<Time>,
+) {
+    // more than one worker can ask for an empty queen spot in the same frame
+    let mut crowned_teams = Vec::new();
+    for player in &players {
+        let (
+            entity,
+            Player {
+                player_controller, ..
+            },
+            &team,
+            is_queen,
+            ..,
+            queen_request,
+        ) = player;
+        if player_controller.is_bot()
+            || !action_states
+                .get(entity)
+                .is_ok_and(|action_state| action_state.just_pressed(&Action::ToggleQueen))
+        {
+            continue;
+        }
+
+        if is_queen {
+            lobby.respawn(&player, team, false);
+            lobby.crown_next_queen(team, &players, entity);
+        } else if let Some(queen_request) = queen_request {
+            lobby
+                .commands
+                .entity(queen_request.label)
+                .despawn_recursive();
+            lobby.commands.entity(entity).remove::<QueenRequest>();
+        } else if crowned_teams.contains(&team)
+            || players.iter().any(|(_, queen, &queen_team, is_queen, ..)| {
+                queen_team == team && is_queen && !queen.player_controller.is_bot()
+            })
+        {
+            // the spot is taken, so wait for the queen to give it up
+            let label = lobby.spawn_label(entity, "queen?", QUEEN_REQUEST_LABEL_Y);
+            lobby.commands.entity(entity).insert(QueenRequest {
+                label,
+                requested_at: time.elapsed_seconds(),
+            });
+        } else {
+            // a bot queen steps aside for a human
+            for bot_queen in &players {
+                if *bot_queen.2 == team && bot_queen.3 && bot_queen.1.player_controller.is_bot() {
+                    lobby.respawn(&bot_queen, team, false);
+                }
+            }
+            lobby.respawn(&player, team, true);
+            crowned_teams.push(team);
+        }
+    }
+}
+
+fn swap_teams(
+    mut ev_swap_team: EventReader<SwapTeamEvent>,
+    players: Query<LobbyPlayer>,
+    mut delayed_player_spawners: Query<(&mut DelayedPlayerSpawner, Has<Waiting>)>,
+    mut lobby: LobbyCommands,
+    game_settings: Res<GameSettings>,
+) {
+    for ev in ev_swap_team.read() {
+        let humans = humans_on_team(
+            ev.team,
+            players.iter().map(|(_, player, team, ..)| (player, team)),
+            delayed_player_spawners
+                .iter()
+                .filter(|(_, is_waiting)| !is_waiting)
+                .map(|(spawner, _)| spawner),
+        );
+        // players in line can change the team they wait for, but nobody swaps into a full team
+        let team_is_full = humans >= game_settings.team_size;
+        for player in &players {
+            let (entity, swapping_player, &team, is_queen, ..) = player;
+            if swapping_player.player_controller != ev.player_controller
+                || team == ev.team
+                || team_is_full
+            {
+                continue;
+            }
+            // players join the other team as workers and can ask for the queen spot there
+            lobby.respawn(&player, ev.team, false);
+            if is_queen {
+                lobby.crown_next_queen(team, &players, entity);
+            }
+        }
+        for (mut delayed_player_spawner, is_waiting) in &mut delayed_player_spawners {
+            if delayed_player_spawner.event.player_controller == ev.player_controller
+                && (is_waiting || !team_is_full)
+            {
+                delayed_player_spawner.event.team = ev.team;
+                delayed_player_spawner.event.is_queen = false;
+            }
+        }
+    }
+}
+
+fn shuffle_teams(
+    mut ev_shuffle_teams: EventReader<ShuffleTeamsEvent>,
+    players: Query<LobbyPlayer>,
+    mut lobby: LobbyCommands,
+    game_settings: Res<GameSettings>,
+) {
+    if ev_shuffle_teams.read().count() == 0 {
+        return;
+    }
+    let mut rng = rand::thread_rng();
+    let mut humans: Vec<_> = players
+        .iter()
+        .filter(|(_, player, ..)| !player.player_controller.is_bot())
+        .collect();
+    humans.shuffle(&mut rng);
+    // queens are dealt out first so each team gets one of them
+    humans.sort_by_key(|(_, _, _, is_queen, ..)| !is_queen);
+    let mut teams: Vec<Team> = game_settings.teams().collect();
+    teams.shuffle(&mut rng);
+
+    let mut team_sizes = vec![0; teams.len()];
+    for human in humans {
+        let index = (0..teams.len())
+            .min_by_key(|&index| team_sizes[index])
+            .unwrap_or_default();
+        team_sizes[index] += 1;
+        if *human.2 != teams[index] {
+            lobby.respawn(&human, teams[index], human.3);
+        }
+    }
+}
+
+fn clear_lobby(
+    mut commands: Commands,
+    ready_players: Query<(Entity, &Ready)>,
+    queen_requests: Query<(Entity, &QueenRequest)>,
+) {
+    for (entity, ready) in &ready_players {
+        commands.entity(ready.label).despawn_recursive();
+        commands.entity(entity).remove::<Ready>();
+    }
+    for (entity, queen_request) in &queen_requests {
+        commands.entity(queen_request.label).despawn_recursive();
+        commands.entity(entity).remove::<QueenRequest>();
+    }
+}
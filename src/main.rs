@@ -2,30 +2,61 @@
 
 mod animation;
 mod berries;
+mod bot;
+mod clock;
+mod combat;
+mod config;
+mod controls;
+mod editor;
 mod gates;
+mod generator;
+mod hive;
 mod join;
+mod keyboard;
+mod lobby;
+mod map;
 #[cfg(feature = "bevy_midi")]
 mod midi;
 mod platforms;
 mod player;
+mod queue;
+mod reconnect;
+mod rumble;
 mod settings;
 mod ship;
+mod teams;
+mod touch;
+mod validate;
+mod weapons;
 
 use animation::AnimationPlugin;
 use berries::BerriesPlugin;
 use bevy::{prelude::*, render::camera::ScalingMode, window::WindowResolution};
 use bevy_inspector_egui::bevy_egui::EguiPlugin;
+use bot::BotPlugin;
+use clock::{ClockPlugin, Tiebreak};
+use controls::ControlsPlugin;
+use editor::EditorPlugin;
 // use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier2d::prelude::*;
 use gates::GatePlugin;
+use hive::HivePlugin;
 use iyes_perf_ui::{diagnostics::PerfUiEntryFPS, PerfUiPlugin, PerfUiRoot};
 use join::JoinPlugin;
+use lobby::LobbyPlugin;
+use map::{Map, MapLibrary, MapPlugin, NextMap};
 #[cfg(feature = "bevy_midi")]
 use midi::MidiPlugin;
 use platforms::PlatformsPlugin;
-use player::{PlayerPlugin, Team};
-use settings::SettingsPlugin;
+use player::PlayerPlugin;
+use queue::QueuePlugin;
+use reconnect::ReconnectPlugin;
+use rumble::RumblePlugin;
+use settings::{GameSettings, SettingsPlugin};
 use ship::ShipPlugin;
+use teams::Team;
+use touch::TouchPlugin;
+use weapons::WeaponsPlugin;
 
 const WINDOW_WIDTH: f32 = 1920.0;
 const WINDOW_HEIGHT: f32 = 1016.0;
@@ -34,12 +65,16 @@ pub const WINDOW_BOTTOM_Y: f32 = WINDOW_HEIGHT / -2.0;
 pub const WINDOW_LEFT_X: f32 = WINDOW_WIDTH / -2.0;
 pub const WINDOW_TOP_Y: f32 = WINDOW_HEIGHT / 2.0;
 pub const WINDOW_RIGHT_X: f32 = WINDOW_WIDTH / 2.0;
-
-const COLOR_BACKGROUND: Color = Color::rgb(0.298, 0.737, 0.937);
+pub const PIXELS_PER_METER: f32 = 100.0;
 
 fn main() {
+    // `validate [map files]` checks maps for spots workers cannot reach instead of playing
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "validate") {
+        std::process::exit(validate::run(&args[1..]));
+    }
+
     App::new()
-        .insert_resource(ClearColor(COLOR_BACKGROUND))
         .init_state::<GameState>()
         .add_plugins(
             DefaultPlugins
@@ -55,16 +90,30 @@ fn main() {
                 .set(ImagePlugin::default_nearest()),
         )
         .add_plugins((
-            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0),
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(PIXELS_PER_METER),
             // RapierDebugRenderPlugin::default(),
+            MapPlugin,
             PlatformsPlugin,
             PlayerPlugin,
             AnimationPlugin,
             BerriesPlugin,
             ShipPlugin,
             GatePlugin,
+            RumblePlugin,
+            WeaponsPlugin,
+            HivePlugin,
+            ClockPlugin,
+        ))
+        .add_plugins((
             JoinPlugin,
+            LobbyPlugin,
+            QueuePlugin,
+            BotPlugin,
+            ReconnectPlugin,
+            ControlsPlugin,
             SettingsPlugin,
+            EditorPlugin,
+            TouchPlugin,
             #[cfg(feature = "bevy_midi")]
             MidiPlugin,
         ))
@@ -85,6 +134,8 @@ enum GameState {
     Join,
     Play,
     GameOver,
+    /// Building a map, players stay around to try it out once the lobby opens again
+    Editor,
 }
 
 fn setup(mut commands: Commands) {
@@ -109,6 +160,8 @@ pub enum WinCondition {
     Military,
     Economic,
     Ship,
+    /// Time ran out and the tiebreak picked the winner
+    Time(Tiebreak),
 }
 
 #[derive(Event)]
@@ -138,16 +191,20 @@ fn set_win_text(
             font_size: 60.0,
             color: win_event.team.color(),
         };
+        let text = match win_event.win_condition {
+            WinCondition::Time(tiebreak) => {
+                format!(
+                    "{} wins on time by {}",
+                    win_event.team.name(),
+                    tiebreak.label()
+                )
+            }
+            win_condition => format!("{:?} victory by {}", win_condition, win_event.team.name()),
+        };
         commands.spawn((
             WinText,
             Text2dBundle {
-                text: Text::from_section(
-                    format!(
-                        "{:?} victory by {:?}",
-                        win_event.win_condition, win_event.team
-                    ),
-                    text_style.clone(),
-                ),
+                text: Text::from_section(text, text_style.clone()),
                 transform: Transform::from_translation(Vec3::new(0.0, 0.0, 100.0)),
                 ..Default::default()
             },
@@ -169,11 +226,16 @@ struct NextGameTimer {
     timer: Timer,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn start_next_game(
     mut next_game_timers: Query<(Entity, &mut NextGameTimer)>,
     time: Res<Time>,
     mut commands: Commands,
     mut next_state: ResMut<NextState<GameState>>,
+    next_map: Res<NextMap>,
+    library: Res<MapLibrary>,
+    game_settings: Res<GameSettings>,
+    mut map: ResMut<Map>,
 ) {
     for (entity, mut next_game_timer) in &mut next_game_timers {
         next_game_timer.timer.tick(time.delta());
@@ -181,6 +243,12 @@ fn start_next_game(
         if next_game_timer.timer.finished() {
             commands.entity(entity).despawn();
             next_state.set(GameState::Join);
+            // the map is rebuilt as the game that just ended is cleared away
+            if let Some(next_map) = next_map.get(&game_settings, &library) {
+                if next_map.name != map.name {
+                    *map = next_map.clone();
+                }
+            }
         }
     }
 }
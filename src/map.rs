@@ -0,0 +1,380 @@
+use bevy::prelude::*;
+use leafwing_input_manager::action_state::ActionState;
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    gates::GateKind,
+    hive::HiveRole,
+    player::{Action, Player},
+    settings::GameSettings,
+    teams::{Team, TEAMS},
+    weapons::Weapon,
+    GameState, WinEvent,
+};
+
+const MAPS_DIR: &str = "assets/maps";
+/// Built in so the game still has its arenas when the map files are missing or broken.
+/// The first one is played until the playlist picks another.
+const BUILT_IN_MAPS: [&str; 3] = [
+    include_str!("../assets/maps/day.ron"),
+    include_str!("../assets/maps/dusk.ron"),
+    include_str!("../assets/maps/night.ron"),
+];
+const NEXT_MAP_FONT_SIZE: f32 = 30.0;
+const NEXT_MAP_Y: f32 = -60.0;
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        let library = MapLibrary::load();
+        app.insert_resource(library.maps[0].clone())
+            .insert_resource(library)
+            .init_resource::<NextMap>()
+            .add_systems(
+                Update,
+                (
+                    paint_background,
+                    record_winner.run_if(in_state(GameState::Play)),
+                    let_losers_pick.run_if(in_state(GameState::GameOver)),
+                    show_next_map,
+                ),
+            )
+            .add_systems(OnEnter(GameState::GameOver), pick_next_map)
+            .add_systems(OnExit(GameState::GameOver), clear_next_map);
+    }
+}
+
+/// How the map for the next game is picked from the playlist
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MapRotation {
+    /// Maps are played in the order of the playlist
+    #[default]
+    Sequential,
+    /// Any map from the playlist but the one just played
+    Random,
+    /// The playlist goes on in order, but the losing teams can flip through it while the
+    /// game is over
+    LoserPicks,
+}
+
+impl MapRotation {
+    pub const ALL: [MapRotation; 3] = [
+        MapRotation::Sequential,
+        MapRotation::Random,
+        MapRotation::LoserPicks,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MapRotation::Sequential => "in order",
+            MapRotation::Random => "random",
+            MapRotation::LoserPicks => "losers pick",
+        }
+    }
+}
+
+/// Every map that can go on the playlist, the built-in ones first
+#[derive(Resource)]
+pub struct MapLibrary {
+    pub maps: Vec<Map>,
+}
+
+impl MapLibrary {
+    /// The built-in maps, replaced by map files of the same name, and then every other map file
+    pub fn load() -> Self {
+        let mut library = Self {
+            maps: Map::built_in(),
+        };
+        for map in read_map_files() {
+            library.insert(map);
+        }
+        library
+    }
+
+    /// Adds a map, replacing the one with the same name
+    pub fn insert(&mut self, map: Map) {
+        match self.maps.iter_mut().find(|known| known.name == map.name) {
+            Some(known) => *known = map,
+            None => self.maps.push(map),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Map> {
+        self.maps.iter().find(|map| map.name == name)
+    }
+}
+
+/// The map from the playlist that is played once the game that just ended is cleared away
+#[derive(Resource, Default)]
+pub struct NextMap {
+    /// Where the map is on the playlist
+    index: Option<usize>,
+    winner: Option<Team>,
+}
+
+impl NextMap {
+    pub fn get<'a>(
+        &self,
+        game_settings: &GameSettings,
+        library: &'a MapLibrary,
+    ) -> Option<&'a Map> {
+        let name = game_settings.playlist.get(self.index?)?;
+        library.get(name)
+    }
+}
+
+#[derive(Component)]
+struct NextMapText;
+
+/// Everything that makes up an arena, positions are in pixels from the middle of the screen
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Map {
+    pub name: String,
+    pub background: Color,
+    pub platforms: Vec<PlatformLayout>,
+    /// Where the bottom middle berry of each bunch hangs
+    pub berry_bunches: Vec<Vec2>,
+    pub gates: Vec<GateLayout>,
+    pub ship: ShipLayout,
+    /// Platforms that keep the queens apart until the game starts
+    pub join_platforms: Vec<PlatformLayout>,
+    /// The layout of each team, in the same order as `TEAMS`
+    pub teams: Vec<TeamLayout>,
+}
+
+impl Map {
+    pub fn built_in() -> Vec<Map> {
+        BUILT_IN_MAPS
+            .iter()
+            .map(|contents| ron::from_str(contents).expect("the built-in maps are valid"))
+            .collect()
+    }
+
+    pub fn team(&self, team: Team) -> &TeamLayout {
+        &self.teams[team.index()]
+    }
+
+    pub fn team_mut(&mut self, team: Team) -> &mut TeamLayout {
+        &mut self.teams[team.index()]
+    }
+
+    /// Writes the map to a file in `MAPS_DIR` named after it, where it is found next time
+    pub fn save(&self) {
+        let file_name: String = self
+            .name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
+        config::save(&format!("{MAPS_DIR}/{file_name}.ron"), self);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlatformLayout {
+    pub position: Vec2,
+    pub size: Vec2,
+}
+
+impl PlatformLayout {
+    /// Floors are tiled sideways, walls upwards
+    pub fn is_floor(&self) -> bool {
+        self.size.x >= self.size.y
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GateLayout {
+    pub position: Vec2,
+    pub kind: GateKind,
+    /// Hands out this weapon instead of the one in the settings
+    #[serde(default)]
+    pub weapon: Option<Weapon>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShipLayout {
+    pub start: Vec2,
+    /// How far left the ship has to go for teams riding it to the left
+    pub left_goal: f32,
+    /// How far right the ship has to go for teams riding it to the right
+    pub right_goal: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TeamLayout {
+    pub hives: Vec<HiveLayout>,
+    pub berry_cells: BerryCellsLayout,
+    pub join_gate: Vec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HiveLayout {
+    pub position: Vec2,
+    pub role: HiveRole,
+}
+
+/// Berry cells fill up in columns of `rows` cells, from `position` away from the middle
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BerryCellsLayout {
+    pub position: Vec2,
+    pub rows: usize,
+}
+
+/// Reads every map file in `MAPS_DIR`, skipping the ones that cannot be played
+#[cfg(not(target_arch = "wasm32"))]
+fn read_map_files() -> Vec<Map> {
+    let Ok(entries) = std::fs::read_dir(MAPS_DIR) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let contents = std::fs::read_to_string(&path).ok()?;
+            match ron::from_str::<Map>(&contents) {
+                Ok(map) if map.teams.len() >= TEAMS.len() => Some(map),
+                Ok(map) => {
+                    warn!(
+                        "{} lays out {} teams but {} can play, skipping it",
+                        path.display(),
+                        map.teams.len(),
+                        TEAMS.len()
+                    );
+                    None
+                }
+                Err(err) => {
+                    warn!("could not read {}, skipping it: {err}", path.display());
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_map_files() -> Vec<Map> {
+    Vec::new()
+}
+
+fn paint_background(map: Res<Map>, mut clear_color: ResMut<ClearColor>) {
+    if map.is_changed() {
+        clear_color.0 = map.background;
+    }
+}
+
+fn record_winner(mut ev_win: EventReader<WinEvent>, mut next_map: ResMut<NextMap>) {
+    for win_event in ev_win.read() {
+        next_map.winner.get_or_insert(win_event.team);
+    }
+}
+
+fn pick_next_map(
+    mut next_map: ResMut<NextMap>,
+    map: Res<Map>,
+    game_settings: Res<GameSettings>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let playlist = &game_settings.playlist;
+    let current = playlist.iter().position(|name| *name == map.name);
+    next_map.index = match game_settings.map_rotation {
+        MapRotation::Random => (0..playlist.len())
+            .filter(|&index| Some(index) != current || playlist.len() == 1)
+            .choose(&mut rand::thread_rng()),
+        MapRotation::Sequential | MapRotation::LoserPicks => match current {
+            Some(current) => Some((current + 1) % playlist.len()),
+            None => (!playlist.is_empty()).then_some(0),
+        },
+    };
+    commands.spawn((
+        NextMapText,
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: NEXT_MAP_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            ),
+            transform: Transform::from_xyz(0.0, NEXT_MAP_Y, 100.0),
+            ..Default::default()
+        },
+    ));
+}
+
+/// Players on the teams that lost flip through the playlist with left and right
+fn let_losers_pick(
+    players: Query<(&Player, &Team, &ActionState<Action>)>,
+    mut next_map: ResMut<NextMap>,
+    game_settings: Res<GameSettings>,
+) {
+    let playlist_len = game_settings.playlist.len();
+    if game_settings.map_rotation != MapRotation::LoserPicks || playlist_len == 0 {
+        return;
+    }
+    for (player, &team, action_state) in &players {
+        if player.player_controller.is_bot()
+            || Some(team) == next_map.winner
+            || !action_state.just_pressed(&Action::Move)
+        {
+            continue;
+        }
+        let index = next_map.index.unwrap_or_default();
+        next_map.index = Some(if action_state.value(&Action::Move) < 0.0 {
+            (index + playlist_len - 1) % playlist_len
+        } else {
+            (index + 1) % playlist_len
+        });
+    }
+}
+
+fn show_next_map(
+    mut next_map_texts: Query<&mut Text, With<NextMapText>>,
+    next_map: Res<NextMap>,
+    game_settings: Res<GameSettings>,
+    library: Res<MapLibrary>,
+) {
+    let Some(map) = next_map.get(&game_settings, &library) else {
+        return;
+    };
+    for mut text in &mut next_map_texts {
+        text.sections[0].value = if game_settings.map_rotation == MapRotation::LoserPicks {
+            format!("Next map: < {} >", map.name)
+        } else {
+            format!("Next map: {}", map.name)
+        };
+    }
+}
+
+fn clear_next_map(
+    mut next_map: ResMut<NextMap>,
+    next_map_texts: Query<Entity, With<NextMapText>>,
+    mut commands: Commands,
+) {
+    *next_map = NextMap::default();
+    for next_map_text in &next_map_texts {
+        commands.entity(next_map_text).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_maps_lay_out_every_team() {
+        for map in Map::built_in() {
+            assert_eq!(map.teams.len(), TEAMS.len(), "{}", map.name);
+            assert!(map.teams.iter().all(|layout| !layout.hives.is_empty()));
+        }
+    }
+}
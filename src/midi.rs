@@ -1,144 +1,693 @@
-use bevy::{prelude::*, utils::HashSet};
-use bevy_midi::input::{MidiData, MidiInput, MidiInputPlugin, MidiInputSettings};
-use leafwing_input_manager::action_state::ActionState;
+use std::{
+    collections::BTreeMap,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use bevy::{input::common_conditions::input_toggle_active, prelude::*, utils::HashSet};
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+use bevy_midi::{input::MidiData, types::MidiMessage};
+use leafwing_input_manager::{action_state::ActionState, plugin::InputManagerSystem};
+use midir::{MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    player::{Action, PlayerController, Queen, SpawnPlayerEvent, Team},
+    bot::BotIds,
+    config,
+    join::{cancel_delayed_spawn, join_team, joined_team},
+    lobby::SwapTeamEvent,
+    player::{
+        press_with_value, Action, DelayedPlayerSpawner, Direction, PlayerController, Queen,
+        SpawnPlayerEvent,
+    },
+    settings::GameSettings,
+    teams::Team,
     GameState,
 };
 
+const MIDI_CONFIG_PATH: &str = "midi.ron";
+const MIDI_CLIENT_NAME: &str = "killer queen";
+/// Jump strength of the softest note when jumps are velocity sensitive
+const MIN_JUMP_STRENGTH: f32 = 0.3;
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
 pub struct MidiPlugin;
 
 impl Plugin for MidiPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
+            PreUpdate,
+            (
+                read_midi_connections,
+                handle_keyboard_presses.after(InputManagerSystem::Update),
+            )
+                .chain(),
+        )
+        .add_systems(
             Update,
-            (handle_keyboard_presses, connect_to_last_input_port),
+            (
+                refresh_midi_ports.run_if(on_event::<RefreshMidiPortsEvent>()),
+                connect_to_selected_input_ports,
+                show_midi_settings.run_if(input_toggle_active(false, KeyCode::Escape)),
+            )
+                .chain(),
         )
-        .init_resource::<MidiInputSettings>()
+        .add_event::<MidiData>()
+        .add_event::<RefreshMidiPortsEvent>()
         .init_resource::<JoinedOctaves>()
-        .add_plugins(MidiInputPlugin);
+        .init_resource::<MidiPorts>()
+        .init_non_send_resource::<MidiConnections>()
+        .insert_resource(config::load::<MidiConfig>(MIDI_CONFIG_PATH))
+        .add_systems(Startup, refresh_midi_ports);
     }
 }
 
 #[derive(Resource, Default)]
 pub struct JoinedOctaves(pub HashSet<u8>);
 
-fn connect_to_last_input_port(input: Res<MidiInput>) {
-    if input.ports().len() == 0 {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MidiAction {
+    MoveLeft,
+    MoveRight,
+    JoinYellow,
+    JoinPurple,
+    Jump,
+    Dive,
+    Ready,
+    ToggleQueen,
+    Attack,
+    Throw,
+}
+
+impl MidiAction {
+    const ALL: [MidiAction; 10] = [
+        MidiAction::MoveLeft,
+        MidiAction::MoveRight,
+        MidiAction::JoinYellow,
+        MidiAction::JoinPurple,
+        MidiAction::Jump,
+        MidiAction::Dive,
+        MidiAction::Ready,
+        MidiAction::ToggleQueen,
+        MidiAction::Attack,
+        MidiAction::Throw,
+    ];
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiConfig {
+    /// Names of the ports to connect to, the last port is used when none is picked
+    pub ports: Vec<String>,
+    /// The action of each note within an octave, from 0 for C up to 11 for B
+    pub notes: BTreeMap<u8, MidiAction>,
+    /// Soft notes make short hops and hard notes full jumps
+    pub velocity_sensitive: bool,
+}
+
+impl Default for MidiConfig {
+    fn default() -> Self {
+        Self {
+            ports: Vec::new(),
+            notes: BTreeMap::from([
+                (0, MidiAction::MoveLeft),
+                (1, MidiAction::JoinYellow),
+                (2, MidiAction::MoveRight),
+                (3, MidiAction::JoinPurple),
+                (4, MidiAction::Jump),
+                (5, MidiAction::Dive),
+                (6, MidiAction::Attack),
+                (7, MidiAction::ToggleQueen),
+                (8, MidiAction::Throw),
+                (9, MidiAction::Ready),
+            ]),
+            velocity_sensitive: true,
+        }
+    }
+}
+
+#[derive(Event)]
+struct RefreshMidiPortsEvent;
+
+/// Names of the MIDI ports that can be picked
+#[derive(Resource, Default)]
+struct MidiPorts(Vec<String>);
+
+/// The open MIDI connections, one for each selected port, all sending their notes down one channel
+struct MidiConnections {
+    connections: Vec<(String, MidiInputConnection<()>)>,
+    sender: Sender<MidiData>,
+    receiver: Receiver<MidiData>,
+}
+
+impl Default for MidiConnections {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            connections: Vec::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+fn refresh_midi_ports(mut midi_ports: ResMut<MidiPorts>) {
+    let Ok(input) = MidiInput::new(MIDI_CLIENT_NAME) else {
+        return;
+    };
+    midi_ports.0 = input
+        .ports()
+        .iter()
+        .filter_map(|port| input.port_name(port).ok())
+        .collect();
+}
+
+/// The ports to connect to, which is the last one when none are picked
+fn selected_ports<'a>(midi_config: &MidiConfig, midi_ports: &'a MidiPorts) -> Vec<&'a String> {
+    if midi_config.ports.is_empty() {
+        return midi_ports.0.last().into_iter().collect();
+    }
+    midi_ports
+        .0
+        .iter()
+        .filter(|port_name| midi_config.ports.contains(port_name))
+        .collect()
+}
+
+fn connect_to_selected_input_ports(
+    mut midi_connections: NonSendMut<MidiConnections>,
+    midi_ports: Res<MidiPorts>,
+    midi_config: Res<MidiConfig>,
+) {
+    if !midi_ports.is_changed() && !midi_config.is_changed() {
         return;
     }
-    if input.is_changed() {
-        if let Some((_, port)) = input.ports().get(input.ports().len() - 1) {
-            input.connect(port.clone());
+    let selected = selected_ports(&midi_config, &midi_ports);
+    // dropping a connection closes it
+    midi_connections
+        .connections
+        .retain(|(port_name, _)| selected.contains(&port_name));
+    for port_name in selected {
+        if midi_connections
+            .connections
+            .iter()
+            .any(|(connected, _)| connected == port_name)
+        {
+            continue;
+        }
+        // every connection takes its own client, as connecting consumes it
+        let Ok(input) = MidiInput::new(MIDI_CLIENT_NAME) else {
+            continue;
+        };
+        let Some(port) = input
+            .ports()
+            .into_iter()
+            .find(|port| input.port_name(port).is_ok_and(|name| name == *port_name))
+        else {
+            continue;
+        };
+        let sender = midi_connections.sender.clone();
+        let connection = input.connect(
+            &port,
+            port_name,
+            move |stamp, message, _| {
+                if let &[status, index, velocity] = message {
+                    let _ = sender.send(MidiData {
+                        stamp,
+                        message: MidiMessage {
+                            msg: [status, index, velocity],
+                        },
+                    });
+                }
+            },
+            (),
+        );
+        match connection {
+            Ok(connection) => midi_connections
+                .connections
+                .push((port_name.clone(), connection)),
+            Err(err) => warn!("could not connect to MIDI port {}: {}", port_name, err),
         }
     }
 }
 
+fn read_midi_connections(
+    midi_connections: NonSend<MidiConnections>,
+    mut midi_data: EventWriter<MidiData>,
+) {
+    midi_data.send_batch(midi_connections.receiver.try_iter());
+}
+
+fn show_midi_settings(
+    mut contexts: EguiContexts,
+    midi_ports: Res<MidiPorts>,
+    mut ev_refresh_midi_ports: EventWriter<RefreshMidiPortsEvent>,
+    mut midi_config: ResMut<MidiConfig>,
+    joined_octaves: Res<JoinedOctaves>,
+) {
+    let mut edited = midi_config.clone();
+    egui::Window::new("MIDI").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("ports");
+            if ui.button("refresh").clicked() {
+                ev_refresh_midi_ports.send(RefreshMidiPortsEvent);
+            }
+        });
+        if edited.ports.is_empty() {
+            ui.label("none picked, using the last port");
+        }
+        // picked ports that are unplugged stay listed, so they can still be unpicked
+        let mut port_names: Vec<String> = midi_ports.0.clone();
+        for port_name in &midi_config.ports {
+            if !port_names.contains(port_name) {
+                port_names.push(port_name.clone());
+            }
+        }
+        for port_name in port_names {
+            let mut picked = edited.ports.contains(&port_name);
+            let label = if midi_ports.0.contains(&port_name) {
+                port_name.clone()
+            } else {
+                format!("{} (unplugged)", port_name)
+            };
+            if ui.checkbox(&mut picked, label).changed() {
+                if picked {
+                    edited.ports.push(port_name);
+                } else {
+                    edited.ports.retain(|picked_port| *picked_port != port_name);
+                }
+            }
+        }
+
+        ui.separator();
+        egui::Grid::new("midi notes").show(ui, |ui| {
+            for (note, note_name) in NOTE_NAMES.iter().enumerate() {
+                let note = note as u8;
+                let mut action = edited.notes.get(&note).copied();
+                ui.label(*note_name);
+                egui::ComboBox::from_id_source(note)
+                    .selected_text(
+                        action.map_or(String::from("none"), |action| format!("{:?}", action)),
+                    )
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut action, None, "none");
+                        for option in MidiAction::ALL {
+                            ui.selectable_value(&mut action, Some(option), format!("{:?}", option));
+                        }
+                    });
+                match action {
+                    Some(action) => edited.notes.insert(note, action),
+                    None => edited.notes.remove(&note),
+                };
+                ui.end_row();
+            }
+        });
+
+        ui.checkbox(&mut edited.velocity_sensitive, "velocity sensitive jumps");
+
+        ui.separator();
+        let mut octaves: Vec<&u8> = joined_octaves.0.iter().collect();
+        octaves.sort();
+        ui.label(if octaves.is_empty() {
+            String::from("no octaves joined")
+        } else {
+            format!("joined octaves: {:?}", octaves)
+        });
+    });
+    // only write back real edits, as a change reconnects to the ports
+    if edited != *midi_config {
+        config::save(MIDI_CONFIG_PATH, &edited);
+        *midi_config = edited;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_keyboard_presses(
     mut midi_data: EventReader<MidiData>,
     mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
-    queens: Query<&Team, With<Queen>>,
-    mut action_states: Query<(&mut ActionState<Action>, &PlayerController)>,
-    mut pressed_keys: Local<HashSet<(u8, u8)>>,
+    mut ev_swap_team: EventWriter<SwapTeamEvent>,
+    mut action_states: Query<(
+        &mut ActionState<Action>,
+        &PlayerController,
+        &Team,
+        Has<Queen>,
+    )>,
+    mut delayed_player_spawners: Query<(Entity, &mut DelayedPlayerSpawner)>,
+    mut pressed_keys: Local<HashSet<(MidiAction, u8)>>,
     mut joined_octaves: ResMut<JoinedOctaves>,
+    mut bot_ids: ResMut<BotIds>,
     state: Res<State<GameState>>,
+    midi_config: Res<MidiConfig>,
+    game_settings: Res<GameSettings>,
+    mut commands: Commands,
 ) {
     for data in midi_data.read() {
-        let [_, index, _value] = data.message.msg;
-        let off = index % 12;
-        let octave = index.overflowing_div(12).0;
-
-        match off {
-            1 | 3 => {
-                if data.message.is_note_on() {
-                    if joined_octaves.0.contains(&octave) {
-                        // player is already in the game
-                        if *state.get() != GameState::Join {
-                            return;
-                        }
-                        for (mut action_state, player_controller) in &mut action_states {
-                            if let PlayerController::Midi {
-                                octave: player_octave,
-                            } = player_controller
-                            {
-                                if *player_octave == octave {
-                                    action_state.press(&Action::Disconnect);
-                                }
-                            }
+        let [_, index, velocity] = data.message.msg;
+        // many keyboards release a note by sending it again with no velocity
+        let note_on = data.message.is_note_on() && velocity > 0;
+        let note_off = data.message.is_note_off() || (data.message.is_note_on() && velocity == 0);
+        let octave = index / 12;
+        let player_controller = PlayerController::Midi { octave };
+        let Some(&midi_action) = midi_config.notes.get(&(index % 12)) else {
+            continue;
+        };
+
+        match midi_action {
+            MidiAction::JoinYellow | MidiAction::JoinPurple => {
+                if !note_on {
+                    continue;
+                }
+                let side = if midi_action == MidiAction::JoinYellow {
+                    Direction::Left
+                } else {
+                    Direction::Right
+                };
+                let joined_team = joined_team(
+                    player_controller,
+                    action_states
+                        .iter()
+                        .map(|(_, &controller, &team, _)| (controller, team)),
+                    &delayed_player_spawners,
+                );
+                let already_joined = joined_octaves.0.contains(&octave);
+                let next_team = Team::next_on_side(side, joined_team, game_settings.team_count);
+                if already_joined && (joined_team.is_none() || next_team.is_none()) {
+                    // the note of your own side goes round its teams and leaves after the last one
+                    joined_octaves.0.remove(&octave);
+                    for (mut action_state, &controller, ..) in &mut action_states {
+                        if controller == player_controller {
+                            action_state.press(&Action::Disconnect);
                         }
-                        joined_octaves.0.remove(&octave);
-                        return;
                     }
-                    let team = if off == 1 { Team::Yellow } else { Team::Purple };
-                    let is_queen = !queens.iter().any(|&queen_team| queen_team == team);
-                    ev_spawn_players.send(SpawnPlayerEvent {
-                        team,
-                        is_queen,
-                        player_controller: PlayerController::Midi { octave },
-                        delay: 0.0,
-                        start_invincible: false,
-                    });
-                    joined_octaves.0.insert(octave);
+                    cancel_delayed_spawn(
+                        &mut commands,
+                        &mut delayed_player_spawners,
+                        player_controller,
+                        state.get(),
+                        &mut bot_ids,
+                    );
+                    pressed_keys.retain(|&(_, key_octave)| key_octave != octave);
+                    continue;
                 }
+                let Some(team) = next_team else {
+                    continue;
+                };
+                join_team(
+                    player_controller,
+                    team,
+                    already_joined,
+                    &mut ev_spawn_players,
+                    &mut ev_swap_team,
+                );
+                joined_octaves.0.insert(octave);
             }
-            // move both direction and dive
-            0 | 2 | 5 => {
-                if data.message.is_note_on() {
-                    pressed_keys.insert((off, octave));
-                } else if data.message.is_note_off() {
-                    pressed_keys.remove(&(off, octave));
+            MidiAction::MoveLeft | MidiAction::MoveRight | MidiAction::Dive => {
+                if note_on {
+                    pressed_keys.insert((midi_action, octave));
+                } else if note_off {
+                    pressed_keys.remove(&(midi_action, octave));
                 }
             }
-            4 => {
-                if data.message.is_note_on() {
-                    for (mut action_state, player_controller) in &mut action_states {
-                        if let PlayerController::Midi {
-                            octave: player_octave,
-                        } = player_controller
-                        {
-                            if *player_octave == octave {
-                                action_state.press(&Action::Jump);
-                            }
-                        }
+            MidiAction::Jump
+            | MidiAction::Ready
+            | MidiAction::ToggleQueen
+            | MidiAction::Attack
+            | MidiAction::Throw => {
+                if !note_on {
+                    continue;
+                }
+                let (action, value) = match midi_action {
+                    MidiAction::Jump if midi_config.velocity_sensitive => (
+                        Action::Jump,
+                        MIN_JUMP_STRENGTH + (1.0 - MIN_JUMP_STRENGTH) * f32::from(velocity) / 127.0,
+                    ),
+                    MidiAction::Ready => (Action::Ready, 1.0),
+                    MidiAction::ToggleQueen => (Action::ToggleQueen, 1.0),
+                    MidiAction::Attack => (Action::Attack, 1.0),
+                    MidiAction::Throw => (Action::Throw, 1.0),
+                    _ => (Action::Jump, 1.0),
+                };
+                for (mut action_state, &controller, ..) in &mut action_states {
+                    if controller == player_controller {
+                        press_with_value(&mut action_state, &action, value);
                     }
                 }
             }
-            _ => (),
         }
     }
-    for (pressed_key, octave) in &pressed_keys {
-        let mut value = 0.0;
-        let mut action = Action::Move;
-        match pressed_key {
-            0 | 2 => {
-                value = if *pressed_key == 0 { -1.0 } else { 1.0 };
-                action = Action::Move;
-            }
-            // 5 => {
-            //     value = 1.0;
-            //     action = Action::Dive;
-            // }
-            _ => (),
-        }
-        for (mut action_state, player_controller) in &mut action_states {
-            if let PlayerController::Midi {
-                octave: player_octave,
-            } = player_controller
+    // held notes are pressed again every frame, as the empty input map releases them
+    for &(midi_action, octave) in pressed_keys.iter() {
+        let (action, value) = match midi_action {
+            MidiAction::MoveLeft => (Action::Move, -1.0),
+            MidiAction::MoveRight => (Action::Move, 1.0),
+            MidiAction::Dive => (Action::Dive, 1.0),
+            _ => continue,
+        };
+        for (mut action_state, &controller, _, is_queen) in &mut action_states {
+            // only queens can dive, just like with the other controllers
+            if controller == (PlayerController::Midi { octave })
+                && (action != Action::Dive || is_queen)
             {
-                if player_octave == octave {
-                    let action_data = action_state.action_data_mut_or_default(&action);
-                    // Consumed actions cannot be pressed until they are released
-                    if action_data.consumed {
-                        return;
-                    }
-                    if action_data.state.released() {
-                        action_data.timing.flip();
-                    }
-                    action_data.state.press();
-                    action_data.value = value;
-                }
+                press_with_value(&mut action_state, &action, value);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::{add_delayed_player_spawners, Player};
+
+    const NOTE_ON: u8 = 0x90;
+    const NOTE_OFF: u8 = 0x80;
+    const OCTAVE: u8 = 5;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_state::<GameState>()
+            .add_event::<MidiData>()
+            .add_event::<SpawnPlayerEvent>()
+            .add_event::<SwapTeamEvent>()
+            .init_resource::<JoinedOctaves>()
+            .init_resource::<BotIds>()
+            .init_resource::<MidiConfig>()
+            .init_resource::<GameSettings>()
+            .add_systems(
+                Update,
+                (handle_keyboard_presses, add_delayed_player_spawners).chain(),
+            );
+        app
+    }
+
+    /// The team a join note for the side picks for a player who has not joined yet
+    fn first_team_on(side: Direction) -> Team {
+        Team::next_on_side(side, None, GameSettings::default().team_count).unwrap()
+    }
+
+    fn set_state(app: &mut App, state: GameState) {
+        app.world.insert_resource(NextState(Some(state)));
+        app.update();
+    }
+
+    /// Sends a note of the given octave, where the offset picks the note within it
+    fn send_note(app: &mut App, status: u8, octave: u8, offset: u8, velocity: u8) {
+        app.world.send_event(MidiData {
+            stamp: 0,
+            message: MidiMessage {
+                msg: [status, octave * 12 + offset, velocity],
+            },
+        });
+        app.update();
+    }
+
+    fn spawn_midi_player(app: &mut App, octave: u8, is_queen: bool) -> Entity {
+        let player_controller = PlayerController::Midi { octave };
+        let mut player = app.world.spawn((
+            ActionState::<Action>::default(),
+            player_controller,
+            Player {
+                player_controller,
+                is_on_ground: true,
+            },
+            first_team_on(Direction::Left),
+        ));
+        if is_queen {
+            player.insert(Queen);
+        }
+        player.id()
+    }
+
+    fn action_state(app: &App, player: Entity) -> &ActionState<Action> {
+        app.world.get::<ActionState<Action>>(player).unwrap()
+    }
+
+    #[test]
+    fn join_note_spawns_player_and_joins_octave() {
+        let mut app = test_app();
+        send_note(&mut app, NOTE_ON, OCTAVE, 3, 100);
+
+        assert!(app.world.resource::<JoinedOctaves>().0.contains(&OCTAVE));
+        let mut spawners = app.world.query::<&DelayedPlayerSpawner>();
+        let spawner = spawners.single(&app.world);
+        assert_eq!(spawner.event.team, first_team_on(Direction::Right));
+        assert_eq!(
+            spawner.event.player_controller,
+            PlayerController::Midi { octave: OCTAVE }
+        );
+    }
+
+    #[test]
+    fn join_note_leaves_during_a_game() {
+        let mut app = test_app();
+        set_state(&mut app, GameState::Play);
+        app.world.resource_mut::<JoinedOctaves>().0.insert(OCTAVE);
+        let player = spawn_midi_player(&mut app, OCTAVE, false);
+
+        send_note(&mut app, NOTE_ON, OCTAVE, 1, 100);
+
+        assert!(action_state(&app, player).pressed(&Action::Disconnect));
+        assert!(app.world.resource::<JoinedOctaves>().0.is_empty());
+    }
+
+    #[test]
+    fn leaving_while_respawning_cancels_the_spawn() {
+        let mut app = test_app();
+        // nothing spawns players in this app, so the player waits in its spawner
+        send_note(&mut app, NOTE_ON, OCTAVE, 1, 100);
+        send_note(&mut app, NOTE_ON, OCTAVE, 1, 100);
+
+        let mut spawners = app.world.query::<&DelayedPlayerSpawner>();
+        assert_eq!(spawners.iter(&app.world).count(), 0);
+        assert!(app.world.resource::<JoinedOctaves>().0.is_empty());
+    }
+
+    #[test]
+    fn leaving_while_respawning_during_a_game_hands_over_to_bot() {
+        let mut app = test_app();
+        set_state(&mut app, GameState::Play);
+        send_note(&mut app, NOTE_ON, OCTAVE, 1, 100);
+        send_note(&mut app, NOTE_ON, OCTAVE, 1, 100);
+
+        let mut spawners = app.world.query::<&DelayedPlayerSpawner>();
+        let spawner = spawners.single(&app.world);
+        assert!(spawner.event.player_controller.is_bot());
+    }
+
+    #[test]
+    fn other_join_note_swaps_team() {
+        let mut app = test_app();
+        send_note(&mut app, NOTE_ON, OCTAVE, 1, 100);
+        send_note(&mut app, NOTE_ON, OCTAVE, 3, 100);
+
+        let swap_team_events = app.world.resource::<Events<SwapTeamEvent>>();
+        let swap_team_event = swap_team_events.get_reader().read(swap_team_events).next();
+        assert!(swap_team_event.is_some_and(|ev| ev.team == first_team_on(Direction::Right)));
+        assert!(app.world.resource::<JoinedOctaves>().0.contains(&OCTAVE));
+    }
+
+    #[test]
+    fn own_join_note_goes_round_the_teams_on_that_side() {
+        let mut app = test_app();
+        app.world.resource_mut::<GameSettings>().team_count = 4;
+        app.world.resource_mut::<JoinedOctaves>().0.insert(OCTAVE);
+        spawn_midi_player(&mut app, OCTAVE, false);
+
+        send_note(&mut app, NOTE_ON, OCTAVE, 1, 100);
+
+        let swap_team_events = app.world.resource::<Events<SwapTeamEvent>>();
+        let swap_team_event = swap_team_events.get_reader().read(swap_team_events).next();
+        assert!(swap_team_event.is_some_and(|ev| {
+            ev.team != first_team_on(Direction::Left) && ev.team.side() == Direction::Left
+        }));
+        assert!(app.world.resource::<JoinedOctaves>().0.contains(&OCTAVE));
+    }
+
+    #[test]
+    fn held_dive_note_dives_queens_only() {
+        let mut app = test_app();
+        let queen = spawn_midi_player(&mut app, OCTAVE, true);
+        let worker = spawn_midi_player(&mut app, OCTAVE + 1, false);
+
+        send_note(&mut app, NOTE_ON, OCTAVE, 5, 100);
+        send_note(&mut app, NOTE_ON, OCTAVE + 1, 5, 100);
+        assert!(action_state(&app, queen).pressed(&Action::Dive));
+        assert!(!action_state(&app, worker).pressed(&Action::Dive));
+
+        // stand in for the input manager, which releases every action each frame
+        app.world
+            .get_mut::<ActionState<Action>>(queen)
+            .unwrap()
+            .release(&Action::Dive);
+        app.update();
+        assert!(action_state(&app, queen).pressed(&Action::Dive));
+
+        app.world
+            .get_mut::<ActionState<Action>>(queen)
+            .unwrap()
+            .release(&Action::Dive);
+        send_note(&mut app, NOTE_OFF, OCTAVE, 5, 0);
+        assert!(!action_state(&app, queen).pressed(&Action::Dive));
+    }
+
+    #[test]
+    fn note_on_without_velocity_releases_the_note() {
+        let mut app = test_app();
+        let player = spawn_midi_player(&mut app, OCTAVE, false);
+
+        send_note(&mut app, NOTE_ON, OCTAVE, 0, 100);
+        assert_eq!(action_state(&app, player).value(&Action::Move), -1.0);
+
+        app.world
+            .get_mut::<ActionState<Action>>(player)
+            .unwrap()
+            .release(&Action::Move);
+        send_note(&mut app, NOTE_ON, OCTAVE, 0, 0);
+        assert!(!action_state(&app, player).pressed(&Action::Move));
+    }
+
+    #[test]
+    fn jump_strength_follows_velocity() {
+        let mut app = test_app();
+        let player = spawn_midi_player(&mut app, OCTAVE, false);
+
+        send_note(&mut app, NOTE_ON, OCTAVE, 4, 127);
+        assert!(action_state(&app, player).just_pressed(&Action::Jump));
+        assert_eq!(action_state(&app, player).value(&Action::Jump), 1.0);
+
+        app.world
+            .get_mut::<ActionState<Action>>(player)
+            .unwrap()
+            .release(&Action::Jump);
+        send_note(&mut app, NOTE_ON, OCTAVE, 4, 1);
+        let soft_jump = action_state(&app, player).value(&Action::Jump);
+        assert!(soft_jump >= MIN_JUMP_STRENGTH && soft_jump < 0.5);
+
+        app.world.resource_mut::<MidiConfig>().velocity_sensitive = false;
+        app.world
+            .get_mut::<ActionState<Action>>(player)
+            .unwrap()
+            .release(&Action::Jump);
+        send_note(&mut app, NOTE_ON, OCTAVE, 4, 1);
+        assert_eq!(action_state(&app, player).value(&Action::Jump), 1.0);
+    }
+
+    #[test]
+    fn consumed_move_does_not_block_other_players() {
+        let mut app = test_app();
+        let consumed = spawn_midi_player(&mut app, OCTAVE, false);
+        let other = spawn_midi_player(&mut app, OCTAVE + 1, false);
+        app.world
+            .get_mut::<ActionState<Action>>(consumed)
+            .unwrap()
+            .consume(&Action::Move);
+
+        send_note(&mut app, NOTE_ON, OCTAVE, 2, 100);
+        send_note(&mut app, NOTE_ON, OCTAVE + 1, 2, 100);
+
+        assert!(!action_state(&app, consumed).pressed(&Action::Move));
+        assert!(action_state(&app, other).pressed(&Action::Move));
+    }
+}
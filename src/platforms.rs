@@ -1,9 +1,7 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use crate::{WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_RIGHT_X, WINDOW_TOP_Y, WINDOW_WIDTH};
-
-pub const PLATFORM_HEIGHT: f32 = 20.0;
+use crate::map::Map;
 
 #[derive(Bundle)]
 pub struct PlatformBundle {
@@ -52,156 +50,38 @@ pub struct PlatformsPlugin;
 
 impl Plugin for PlatformsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup);
+        app.add_systems(Update, place_platforms);
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    for sign in [1.0, -1.0] {
-        for (x, y, width) in [
-            // layer 0
-            (0.0, WINDOW_BOTTOM_Y, WINDOW_WIDTH),
-            // layer 1
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 24.0),
-                WINDOW_BOTTOM_Y + WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 12.0,
-            ),
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0),
-                WINDOW_BOTTOM_Y + WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 30.0,
-            ),
-            // layer 2
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 7.0),
-                WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 25.0,
-            ),
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 3.2),
-                WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 20.0,
-            ),
-            // layer 3
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 40.0),
-                WINDOW_BOTTOM_Y + 3.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 20.0,
-            ),
-            (
-                WINDOW_WIDTH / 10.0,
-                WINDOW_BOTTOM_Y + 3.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 20.0,
-            ),
-            // layer 4
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0),
-                WINDOW_BOTTOM_Y + 4.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 5.0,
-            ),
-            // layer 5
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 40.0),
-                WINDOW_BOTTOM_Y + 5.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 20.0,
-            ),
-            (
-                WINDOW_WIDTH / 10.0,
-                WINDOW_BOTTOM_Y + 5.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 20.0,
-            ),
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0),
-                WINDOW_BOTTOM_Y + 5.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 15.0,
-            ),
-            // layer 6
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 8.0),
-                WINDOW_BOTTOM_Y + 6.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 25.0,
-            ),
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 3.2),
-                WINDOW_BOTTOM_Y + 6.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 25.0,
-            ),
-            // layer 7
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 40.0),
-                WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 20.0,
-            ),
-            (
-                WINDOW_WIDTH / 20.0,
-                WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 10.0,
-            ),
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 5.0),
-                WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 15.0,
-            ),
-            // layer 8
-            (
-                (WINDOW_RIGHT_X - WINDOW_WIDTH / 3.2),
-                WINDOW_BOTTOM_Y + 8.0 * WINDOW_HEIGHT / 9.0,
-                WINDOW_WIDTH / 25.0,
-            ),
-            // layer 9 (top)
-            (0.0, WINDOW_TOP_Y, WINDOW_WIDTH),
-        ] {
-            commands.spawn(PlatformBundle::new(
-                x * sign,
-                y,
-                Vec3::new(width, PLATFORM_HEIGHT, 1.0),
-                true,
-                None,
-                &asset_server,
-            ));
-        }
+/// A platform that belongs to the map, as opposed to the ones that only stand during the lobby
+#[derive(Component)]
+struct MapPlatform;
+
+/// Builds the platforms of the map, again whenever another map is loaded
+fn place_platforms(
+    platforms: Query<Entity, With<MapPlatform>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    map: Res<Map>,
+) {
+    if !map.is_changed() {
+        return;
     }
-    for (y, width) in [
-        // layer 1
-        (WINDOW_BOTTOM_Y + WINDOW_HEIGHT / 9.0, WINDOW_WIDTH / 4.0),
-        // layer 2
-        (
-            WINDOW_BOTTOM_Y + 2.0 * WINDOW_HEIGHT / 9.0,
-            WINDOW_WIDTH / 20.0,
-        ),
-        // layer 4
-        (
-            WINDOW_BOTTOM_Y + 4.0 * WINDOW_HEIGHT / 9.0,
-            WINDOW_WIDTH / 20.0,
-        ),
-    ] {
-        commands.spawn(PlatformBundle::new(
-            0.0,
-            y,
-            Vec3::new(width, PLATFORM_HEIGHT, 1.0),
-            true,
-            None,
-            &asset_server,
-        ));
+    for platform in &platforms {
+        commands.entity(platform).despawn();
     }
-    // divider
-    commands.spawn(PlatformBundle::new(
-        0.0,
-        WINDOW_BOTTOM_Y + 8.0 * WINDOW_HEIGHT / 9.0,
-        Vec3::new(PLATFORM_HEIGHT, 2.0 * WINDOW_HEIGHT / 9.0, 1.0),
-        false,
-        None,
-        &asset_server,
-    ));
-    for sign in [-1.0, 1.0] {
-        commands.spawn(PlatformBundle::new(
-            WINDOW_RIGHT_X * sign,
-            WINDOW_BOTTOM_Y + 7.0 * WINDOW_HEIGHT / 9.0,
-            Vec3::new(PLATFORM_HEIGHT, 4.0 * WINDOW_HEIGHT / 9.0, 1.0),
-            false,
-            None,
-            &asset_server,
+    for platform in &map.platforms {
+        commands.spawn((
+            PlatformBundle::new(
+                platform.position.x,
+                platform.position.y,
+                platform.size.extend(1.0),
+                platform.is_floor(),
+                None,
+                &asset_server,
+            ),
+            MapPlatform,
         ));
     }
 }
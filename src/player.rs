@@ -1,31 +1,48 @@
 use std::{f32::MAX, time::Duration};
 
-use bevy::prelude::*;
+use bevy::{
+    ecs::{query::ROQueryItem, system::SystemParam},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use bevy_rapier2d::prelude::*;
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    animation::Animation, berries::Berry, join::remove_player, settings::GameSettings,
-    ship::RidingOnShip, GameState, WinCondition, WinEvent, WINDOW_BOTTOM_Y, WINDOW_HEIGHT,
-    WINDOW_LEFT_X, WINDOW_RIGHT_X, WINDOW_TOP_Y, WINDOW_WIDTH,
+    animation::Animation,
+    berries::Berry,
+    combat::{hit_pair, overlap_normal, resolve_combat, CombatBox, CombatOutcome, Combatant, Role},
+    controls::{input_map, ControlsConfig},
+    hive::{spawn_point, Hive},
+    join::remove_player,
+    keyboard::KeyboardLayout,
+    queue::{humans_on_team, Waiting},
+    rumble::{RumbleEvent, RumbleKind, RumbleTarget},
+    settings::GameSettings,
+    ship::RidingOnShip,
+    teams::{Team, TEAMS},
+    GameState, WinCondition, WinEvent, WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_LEFT_X,
+    WINDOW_RIGHT_X, WINDOW_TOP_Y, WINDOW_WIDTH,
 };
 
-const PLAYER_MAX_VELOCITY_X: f32 = 600.0;
+pub const PLAYER_MAX_VELOCITY_X: f32 = 600.0;
 const PLAYER_MIN_VELOCITY_X: f32 = 40.0;
-const PLAYER_MAX_FALL_SPEED: f32 = 400.0;
+pub const PLAYER_MAX_FALL_SPEED: f32 = 400.0;
 const PLAYER_MAX_DIVE_SPEED: f32 = 1200.0;
 const PLAYER_MAX_RISE_SPEED: f32 = 600.0;
 const PLAYER_FLY_IMPULSE: f32 = 73.0;
 pub const PLAYER_JUMP_IMPULSE: f32 = 46.0;
-const PLAYER_MOVEMENT_IMPULSE_GROUND: f32 = 180.0;
-const PLAYER_MOVEMENT_IMPULSE_AIR: f32 = 115.0;
-const PLAYER_FRICTION_GROUND: f32 = 0.5;
-const PLAYER_FRICTION_AIR: f32 = 0.3;
-const PLAYER_GRAVITY_SCALE: f32 = 15.0;
+pub const PLAYER_MOVEMENT_IMPULSE_GROUND: f32 = 180.0;
+pub const PLAYER_MOVEMENT_IMPULSE_AIR: f32 = 115.0;
+const SPEED_MAX_VELOCITY_X: f32 = 850.0;
+const SPEED_MOVEMENT_MULTIPLIER: f32 = 1.5;
+pub const PLAYER_FRICTION_GROUND: f32 = 0.5;
+pub const PLAYER_FRICTION_AIR: f32 = 0.3;
+pub const PLAYER_GRAVITY_SCALE: f32 = 15.0;
 const DIVE_GRAVITY_SCALE: f32 = 45.0;
 pub const PLAYER_COLLIDER_WIDTH_MULTIPLIER: f32 = 0.4;
-const RESPAWN_DELAY: f32 = 2.0;
-const INVINCIBILITY_DURATION: f32 = 2.0;
+pub const RESPAWN_DELAY: f32 = 2.0;
 
 const SPRITESHEET_COLS: usize = 2;
 const SPRITESHEET_ROWS: usize = 2;
@@ -75,9 +92,11 @@ impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(InputManagerPlugin::<Action>::default())
             .init_resource::<QueenDeaths>()
+            .init_resource::<KilledPlayers>()
             .add_event::<KnockBackEvent>()
             .add_event::<SpawnPlayerEvent>()
             .add_systems(Startup, setup)
+            .add_systems(First, forget_killed_players)
             .add_systems(
                 Update,
                 (
@@ -108,58 +127,101 @@ impl Plugin for PlayerPlugin {
             .add_systems(
                 OnExit(GameState::GameOver),
                 (reset_all_players, reset_queen_lives_counter),
-            );
+            )
+            // nobody should be left riding a ship or standing in a wall the editor moved
+            .add_systems(OnEnter(GameState::Editor), reset_all_players)
+            .add_systems(OnExit(GameState::Editor), reset_all_players);
     }
 }
 
-#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
+#[derive(
+    Actionlike,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Debug,
+    Reflect,
+    Serialize,
+    Deserialize,
+)]
 pub enum Action {
     Move,
     Jump,
     Disconnect,
     Dive,
+    Ready,
+    ToggleQueen,
+    Attack,
+    Throw,
+}
+
+/// Presses an analog action on a player whose `ActionState` is not driven by an `InputMap`
+pub fn press_with_value(action_state: &mut ActionState<Action>, action: &Action, value: f32) {
+    let action_data = action_state.action_data_mut_or_default(action);
+    // Consumed actions cannot be pressed until they are released
+    if action_data.consumed {
+        return;
+    }
+    if action_data.state.released() {
+        action_data.timing.flip();
+    }
+    action_data.state.press();
+    action_data.value = value;
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
     Right,
     Left,
 }
 
-#[derive(Component, PartialEq, Eq, Clone, Copy, Debug)]
-pub enum Team {
-    Yellow,
-    Purple,
-}
-
-impl Team {
-    pub fn color(&self) -> Color {
-        match self {
-            Team::Yellow => Color::rgb(1.0, 0.773, 0.0),
-            Team::Purple => Color::rgb(0.435, 0.0, 1.0),
-        }
-    }
-}
-
 #[derive(Component)]
 pub struct Queen;
 
 #[derive(Component)]
-struct Invincible {
+pub struct Invincible {
     timer: Timer,
     animation_timer: Timer,
 }
 
 #[derive(Default, Resource)]
-pub struct QueenDeaths {
-    yellow_deaths: i32,
-    purple_deaths: i32,
+pub struct QueenDeaths(HashMap<Team, i32>);
+
+impl QueenDeaths {
+    pub fn get(&self, team: Team) -> i32 {
+        self.0.get(&team).copied().unwrap_or(0)
+    }
 }
 
-#[derive(Clone, Copy, Debug, Component)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
 pub enum PlayerController {
     Gamepad(Gamepad),
     Midi { octave: u8 },
+    Keyboard { layout: KeyboardLayout },
+    Touch { pad: u8 },
+    Bot { id: u32 },
+}
+
+impl PlayerController {
+    pub fn is_bot(&self) -> bool {
+        matches!(self, PlayerController::Bot { .. })
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            PlayerController::Gamepad(gamepad) => format!("gamepad {}", gamepad.id),
+            PlayerController::Midi { octave } => format!("octave {octave}"),
+            PlayerController::Keyboard { layout } => {
+                format!("keyboard {:?}", layout).to_lowercase()
+            }
+            PlayerController::Touch { pad } => format!("touch pad {pad}"),
+            PlayerController::Bot { id } => format!("bot {id}"),
+        }
+    }
 }
 
 #[derive(Component)]
@@ -172,8 +234,13 @@ pub struct Player {
 #[derive(Component)]
 pub struct Wings;
 
+/// Players who went through a speed gate, they stay fast until they die
+#[derive(Component)]
+pub struct Speed;
+
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    for team in [Team::Yellow, Team::Purple] {
+    // every team gets a counter, the ones not in play stay empty
+    for team in Team::all(TEAMS.len()) {
         let font = asset_server.load("fonts/FiraSans-Bold.ttf");
         let text_style = TextStyle {
             font: font.clone(),
@@ -184,10 +251,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             Text2dBundle {
                 text: Text::from_section("", text_style.clone()),
                 transform: Transform::from_translation(Vec3::new(
-                    match team {
-                        Team::Yellow => -WINDOW_WIDTH / 20.0,
-                        Team::Purple => WINDOW_WIDTH / 20.0,
-                    },
+                    team.definition().hud_x,
                     WINDOW_TOP_Y - (WINDOW_HEIGHT / 30.0),
                     100.0,
                 )),
@@ -199,8 +263,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 }
 
 fn reset_queen_lives_counter(mut queen_deaths: ResMut<QueenDeaths>) {
-    queen_deaths.yellow_deaths = 0;
-    queen_deaths.purple_deaths = 0;
+    queen_deaths.0.clear();
 }
 
 fn update_queen_lives_counter(
@@ -209,14 +272,15 @@ fn update_queen_lives_counter(
     game_settings: Res<GameSettings>,
 ) {
     for (mut counter_text, counter_team) in counters.iter_mut() {
-        counter_text.sections[0].value = format!(
-            "Lives: {}",
-            game_settings.queen_lives
-                - match counter_team {
-                    Team::Yellow => queen_deaths.yellow_deaths,
-                    Team::Purple => queen_deaths.purple_deaths,
-                }
-        )
+        counter_text.sections[0].value = if game_settings.teams().any(|team| team == *counter_team)
+        {
+            format!(
+                "Lives: {}",
+                game_settings.queen_lives - queen_deaths.get(*counter_team)
+            )
+        } else {
+            String::new()
+        }
     }
 }
 
@@ -227,11 +291,19 @@ fn movement(
         &ActionState<Action>,
         &mut ExternalImpulse,
         &mut Velocity,
+        Has<Speed>,
     )>,
     mut commands: Commands,
     time: Res<Time>,
 ) {
-    for (player_entity, player, action_state, mut impulse, mut velocity) in query.iter_mut() {
+    for (player_entity, player, action_state, mut impulse, mut velocity, is_fast) in
+        query.iter_mut()
+    {
+        let (movement_multiplier, max_velocity_x) = if is_fast {
+            (SPEED_MOVEMENT_MULTIPLIER, SPEED_MAX_VELOCITY_X)
+        } else {
+            (1.0, PLAYER_MAX_VELOCITY_X)
+        };
         if action_state.pressed(&Action::Move)
             && !(action_state.pressed(&Action::Dive) && player.is_on_ground)
         {
@@ -242,11 +314,15 @@ fn movement(
                 commands.entity(player_entity).insert(Direction::Left);
             }
             if player.is_on_ground {
-                impulse.impulse.x +=
-                    joystick_value * PLAYER_MOVEMENT_IMPULSE_GROUND * time.delta_seconds();
+                impulse.impulse.x += joystick_value
+                    * PLAYER_MOVEMENT_IMPULSE_GROUND
+                    * movement_multiplier
+                    * time.delta_seconds();
             } else {
-                impulse.impulse.x +=
-                    joystick_value * PLAYER_MOVEMENT_IMPULSE_AIR * time.delta_seconds();
+                impulse.impulse.x += joystick_value
+                    * PLAYER_MOVEMENT_IMPULSE_AIR
+                    * movement_multiplier
+                    * time.delta_seconds();
             }
         } else {
             // stop the player from moving if joystick is not being pressed and moving slowly
@@ -255,10 +331,7 @@ fn movement(
             }
         }
 
-        velocity.linvel.x = velocity
-            .linvel
-            .x
-            .clamp(-PLAYER_MAX_VELOCITY_X, PLAYER_MAX_VELOCITY_X);
+        velocity.linvel.x = velocity.linvel.x.clamp(-max_velocity_x, max_velocity_x);
     }
 }
 
@@ -275,7 +348,7 @@ fn friction(mut query: Query<(&mut ExternalImpulse, &Velocity, &Player)>, time:
 fn fly(mut query: Query<(&ActionState<Action>, &mut ExternalImpulse), With<Wings>>) {
     for (action_state, mut impulse) in query.iter_mut() {
         if action_state.just_pressed(&Action::Jump) && !action_state.pressed(&Action::Dive) {
-            impulse.impulse.y += PLAYER_FLY_IMPULSE;
+            impulse.impulse.y += PLAYER_FLY_IMPULSE * jump_strength(action_state);
         }
     }
 }
@@ -283,11 +356,22 @@ fn fly(mut query: Query<(&ActionState<Action>, &mut ExternalImpulse), With<Wings
 fn jump(mut query: Query<(&ActionState<Action>, &mut ExternalImpulse, &Player), Without<Wings>>) {
     for (action_state, mut impulse, player) in query.iter_mut() {
         if action_state.just_pressed(&Action::Jump) && player.is_on_ground {
-            impulse.impulse.y += PLAYER_JUMP_IMPULSE;
+            impulse.impulse.y += PLAYER_JUMP_IMPULSE * jump_strength(action_state);
         }
     }
 }
 
+/// Analog jumps such as MIDI notes carry their strength in the action value,
+/// while buttons and bots jump at full strength
+fn jump_strength(action_state: &ActionState<Action>) -> f32 {
+    let value = action_state.value(&Action::Jump);
+    if value > 0.0 {
+        value.min(1.0)
+    } else {
+        1.0
+    }
+}
+
 fn dive(mut queens: Query<(Entity, &ActionState<Action>)>, mut commands: Commands) {
     for (entity, action_state) in &mut queens {
         if action_state.just_pressed(&Action::Dive) {
@@ -418,183 +502,184 @@ pub struct SpawnPlayerEvent {
     pub start_invincible: bool,
 }
 
+/// A player that can be killed, as passed to [`PlayerDeaths::kill`]
+pub type Killable = (
+    Entity,
+    &'static Transform,
+    &'static Player,
+    &'static Team,
+    Has<Berry>,
+    Has<Queen>,
+    Has<Invincible>,
+    Option<&'static RidingOnShip>,
+);
+
+/// Players already killed this frame, so a second hit on them does nothing
+#[derive(Default, Resource)]
+pub struct KilledPlayers(HashSet<Entity>);
+
+fn forget_killed_players(mut killed_players: ResMut<KilledPlayers>) {
+    killed_players.0.clear();
+}
+
+#[derive(SystemParam)]
+pub struct PlayerDeaths<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    queen_deaths: ResMut<'w, QueenDeaths>,
+    killed_players: ResMut<'w, KilledPlayers>,
+    ev_spawn_players: EventWriter<'w, SpawnPlayerEvent>,
+    ev_rumble: EventWriter<'w, RumbleEvent>,
+}
+
+impl PlayerDeaths<'_, '_> {
+    /// Whether the player was already killed this frame
+    pub fn is_killed(&self, entity: Entity) -> bool {
+        self.killed_players.0.contains(&entity)
+    }
+
+    /// Removes the player and sends them back to respawn, unless they are invincible
+    /// or were already killed this frame
+    pub fn kill(&mut self, killed: ROQueryItem<'_, Killable>) {
+        let (
+            entity,
+            transform,
+            player,
+            &team,
+            has_berry,
+            is_queen,
+            is_invincible,
+            maybe_riding_on_ship,
+        ) = killed;
+        if is_invincible || !self.killed_players.0.insert(entity) {
+            return;
+        }
+        if is_queen {
+            *self.queen_deaths.0.entry(team).or_default() += 1;
+            self.ev_rumble.send(RumbleEvent {
+                target: RumbleTarget::Team(team),
+                kind: RumbleKind::QueenLostLife,
+            });
+        }
+        self.ev_rumble.send(RumbleEvent {
+            target: RumbleTarget::Player(player.player_controller),
+            kind: RumbleKind::Death,
+        });
+        remove_player(
+            &mut self.commands,
+            entity,
+            has_berry,
+            transform,
+            &self.asset_server,
+            maybe_riding_on_ship,
+        );
+        self.ev_spawn_players.send(SpawnPlayerEvent {
+            team,
+            is_queen,
+            player_controller: player.player_controller,
+            delay: RESPAWN_DELAY,
+            start_invincible: true,
+        });
+    }
+}
+
 fn players_attack(
     mut collision_events: EventReader<CollisionEvent>,
-    players: Query<
-        (
-            Entity,
-            &Transform,
-            &Player,
-            &Team,
-            Has<Wings>,
-            Has<Berry>,
-            &Direction,
-            &Sprite,
-            Option<&RidingOnShip>,
-            Has<Queen>,
-            Has<Invincible>,
-            &ActionState<Action>,
-        ),
-        With<Player>,
-    >,
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    combat_boxes: Query<(&CombatBox, &Parent)>,
+    players: Query<(
+        Killable,
+        Has<Wings>,
+        &Direction,
+        &Velocity,
+        &ActionState<Action>,
+    )>,
+    rapier_context: Res<RapierContext>,
     mut ev_knockback: EventWriter<KnockBackEvent>,
-    mut queen_deaths: ResMut<QueenDeaths>,
-    mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
+    mut deaths: PlayerDeaths,
 ) {
+    // two players whose hitboxes both land at once still only fight once
+    let mut fought = Vec::new();
     for collision_event in collision_events.read() {
-        if let CollisionEvent::Started(entity1, entity2, _flags) = collision_event {
-            if let (Ok(player1_components), Ok(player2_components)) =
-                (players.get(*entity1), players.get(*entity2))
-            {
-                if player1_components.3 == player2_components.3 {
-                    continue;
-                }
-                let player1_translation = player1_components.1.translation;
-                let player2_translation = player2_components.1.translation;
-                let player1_half_width = player1_components.7.custom_size.unwrap().x / 2.0
-                    * PLAYER_COLLIDER_WIDTH_MULTIPLIER;
-                let player2_half_width = player2_components.7.custom_size.unwrap().x / 2.0
-                    * PLAYER_COLLIDER_WIDTH_MULTIPLIER;
-                let player1_half_height = player1_components.7.custom_size.unwrap().y / 2.0;
-                let player2_half_height = player2_components.7.custom_size.unwrap().y / 2.0;
-
-                let x_diff = (player1_translation.x - player2_translation.x).abs();
-                let y_diff = (player1_translation.y - player2_translation.y).abs();
-
-                let one_player_on_top = (y_diff - (player1_half_height + player2_half_height))
-                    > (x_diff - (player1_half_width + player2_half_width));
-
-                if let Some((killed_player_components, _killer_player_components)) = {
-                    let player1_has_wings = player1_components.4;
-                    let player2_has_wings = player2_components.4;
-                    match (player1_has_wings, player2_has_wings) {
-                        (true, true) => {
-                            // both are queens
-                            if one_player_on_top {
-                                // one player on top
-                                if player1_translation.y > player2_translation.y {
-                                    Some((player2_components, player1_components))
-                                } else {
-                                    Some((player1_components, player2_components))
-                                }
-                            } else {
-                                // hit each other horizontally
-                                let (left_player_components, right_player_components) =
-                                    if player1_translation.x < player2_translation.x {
-                                        (player1_components, player2_components)
-                                    } else {
-                                        (player2_components, player1_components)
-                                    };
-                                let mut apply_knockback = || {
-                                    ev_knockback.send(KnockBackEvent {
-                                        entity: left_player_components.0,
-                                        direction: Direction::Left,
-                                    });
-                                    ev_knockback.send(KnockBackEvent {
-                                        entity: right_player_components.0,
-                                        direction: Direction::Right,
-                                    });
-                                };
-                                let left_player_direction = left_player_components.6;
-                                let right_player_direction = right_player_components.6;
-                                let left_player_diving =
-                                    left_player_components.11.pressed(&Action::Dive);
-                                let right_player_diving =
-                                    right_player_components.11.pressed(&Action::Dive);
-                                match (left_player_diving, right_player_diving) {
-                                    (false, false) => {
-                                        // neither player is diving
-                                        match (left_player_direction, right_player_direction) {
-                                            (Direction::Right, Direction::Right) => Some((
-                                                right_player_components,
-                                                left_player_components,
-                                            )),
-                                            (Direction::Left, Direction::Left) => Some((
-                                                left_player_components,
-                                                right_player_components,
-                                            )),
-                                            _ => {
-                                                // hit swords or backs
-                                                apply_knockback();
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    _ => {
-                                        // queens hit with one or both diving
-                                        apply_knockback();
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-                        (true, false) => Some((player2_components, player1_components)),
-                        (false, true) => Some((player1_components, player2_components)),
-                        (false, false) => {
-                            // both are workers
-                            if !one_player_on_top {
-                                let (left_player_components, right_player_components) =
-                                    if player1_translation.x < player2_translation.x {
-                                        (player1_components, player2_components)
-                                    } else {
-                                        (player2_components, player1_components)
-                                    };
-                                // workers hit
-                                ev_knockback.send(KnockBackEvent {
-                                    entity: left_player_components.0,
-                                    direction: Direction::Left,
-                                });
-                                ev_knockback.send(KnockBackEvent {
-                                    entity: right_player_components.0,
-                                    direction: Direction::Right,
-                                });
-                            }
-                            None
-                        }
-                    }
-                } {
-                    let (
-                        killed_entity,
-                        killed_player_transform,
-                        killed_player,
-                        &killed_player_team,
-                        _,
-                        killed_has_berry,
-                        _,
-                        _,
-                        maybe_riding_on_ship,
-                        killed_player_is_queen,
-                        killed_player_invincible,
-                        _,
-                    ) = killed_player_components;
-                    if killed_player_invincible {
-                        continue;
-                    }
-                    if killed_player_is_queen {
-                        match killed_player_team {
-                            Team::Yellow => queen_deaths.yellow_deaths += 1,
-                            Team::Purple => queen_deaths.purple_deaths += 1,
-                        }
-                    }
-                    remove_player(
-                        &mut commands,
-                        killed_entity,
-                        killed_has_berry,
-                        killed_player_transform,
-                        &asset_server,
-                        maybe_riding_on_ship,
-                    );
-                    ev_spawn_players.send(SpawnPlayerEvent {
-                        team: killed_player_team,
-                        is_queen: killed_player_is_queen,
-                        player_controller: killed_player.player_controller,
-                        delay: RESPAWN_DELAY,
-                        start_invincible: true,
-                    });
+        let CollisionEvent::Started(box1, box2, _flags) = collision_event else {
+            continue;
+        };
+        let (Ok((&combat_box1, parent1)), Ok((&combat_box2, parent2))) =
+            (combat_boxes.get(*box1), combat_boxes.get(*box2))
+        else {
+            continue;
+        };
+        let Some((entity1, entity2)) =
+            hit_pair((combat_box1, parent1.get()), (combat_box2, parent2.get()))
+        else {
+            continue;
+        };
+        let pair = (entity1.min(entity2), entity1.max(entity2));
+        // a player who already died this frame can neither be killed again nor kill
+        if fought.contains(&pair) || deaths.is_killed(entity1) || deaths.is_killed(entity2) {
+            continue;
+        }
+        let (Ok(player1), Ok(player2)) = (players.get(entity1), players.get(entity2)) else {
+            continue;
+        };
+        let (killable1, killable2) = (player1.0, player2.0);
+        if killable1.3 == killable2.3 {
+            continue;
+        }
+        fought.push(pair);
+        let [combatant1, combatant2] =
+            [&player1, &player2].map(|(_, has_wings, direction, velocity, action_state)| {
+                Combatant {
+                    has_wings: *has_wings,
+                    direction: **direction,
+                    diving: action_state.pressed(&Action::Dive),
+                    velocity: velocity.linvel,
                 }
+            });
+        let normal = rapier_context
+            .contact_pair(entity1, entity2)
+            .and_then(|contact_pair| {
+                let manifold = contact_pair
+                    .manifolds()
+                    .find(|manifold| manifold.num_points() > 0)?;
+                // players cannot rotate, so the normal is already in world space
+                Some(if contact_pair.collider1() == entity1 {
+                    manifold.normal()
+                } else {
+                    -manifold.normal()
+                })
+            })
+            // the hitbox reaches past the body, so the bodies need not touch yet
+            .unwrap_or_else(|| {
+                let [hitbox, hurtbox] = [
+                    (&player1, CombatBox::Hitbox),
+                    (&player2, CombatBox::Hurtbox),
+                ]
+                .map(|((killable, has_wings, ..), combat_box)| {
+                    let role = Role::new(killable.5, *has_wings);
+                    Rect::from_center_half_size(
+                        killable.1.translation.truncate(),
+                        role.half_size(combat_box),
+                    )
+                });
+                overlap_normal(hitbox, hurtbox)
+            });
+        match resolve_combat(&combatant1, &combatant2, normal) {
+            CombatOutcome::FirstDies => deaths.kill(killable1),
+            CombatOutcome::SecondDies => deaths.kill(killable2),
+            CombatOutcome::KnockBack(direction) => {
+                ev_knockback.send(KnockBackEvent {
+                    entity: entity1,
+                    direction,
+                });
+                ev_knockback.send(KnockBackEvent {
+                    entity: entity2,
+                    direction: match direction {
+                        Direction::Left => Direction::Right,
+                        Direction::Right => Direction::Left,
+                    },
+                });
             }
+            CombatOutcome::Nothing => {}
         }
     }
 }
@@ -642,17 +727,14 @@ fn check_for_queen_death_win(
     queen_deaths: Res<QueenDeaths>,
     game_settings: Res<GameSettings>,
 ) {
-    let win_condition = WinCondition::Military;
-    if queen_deaths.yellow_deaths >= game_settings.queen_lives {
-        ev_win.send(WinEvent {
-            team: Team::Purple,
-            win_condition,
-        });
-    }
-    if queen_deaths.purple_deaths >= game_settings.queen_lives {
+    // the last team whose queen still has lives left wins
+    let mut standing = game_settings
+        .teams()
+        .filter(|&team| queen_deaths.get(team) < game_settings.queen_lives);
+    if let (Some(team), None) = (standing.next(), standing.next()) {
         ev_win.send(WinEvent {
-            team: Team::Yellow,
-            win_condition,
+            team,
+            win_condition: WinCondition::Military,
         });
     }
 }
@@ -675,45 +757,126 @@ fn wrap_around_screen(mut players: Query<&mut Transform>) {
 }
 
 #[derive(Component)]
-struct DelayedPlayerSpawner {
+pub struct DelayedPlayerSpawner {
     timer: Timer,
-    event: SpawnPlayerEvent,
+    pub event: SpawnPlayerEvent,
 }
 
-fn add_delayed_player_spawners(
+impl DelayedPlayerSpawner {
+    pub fn new(event: SpawnPlayerEvent) -> Self {
+        Self {
+            timer: Timer::from_seconds(event.delay, TimerMode::Once),
+            event,
+        }
+    }
+
+    /// How far along the wait is, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.timer.fraction()
+    }
+}
+
+pub fn add_delayed_player_spawners(
     mut ev_spawn_players: EventReader<SpawnPlayerEvent>,
     mut commands: Commands,
+    players: Query<(&Player, &Team)>,
+    delayed_player_spawners: Query<&DelayedPlayerSpawner, Without<Waiting>>,
+    game_settings: Res<GameSettings>,
+    time: Res<Time>,
 ) {
+    // spawners from earlier events this frame are not in the query yet
+    let mut spawned_this_frame = Vec::new();
     for ev in ev_spawn_players.read() {
-        commands.spawn(DelayedPlayerSpawner {
-            timer: Timer::from_seconds(ev.delay, TimerMode::Once),
-            event: *ev,
-        });
+        let mut spawner = commands.spawn(DelayedPlayerSpawner::new(*ev));
+        if ev.player_controller.is_bot() {
+            continue;
+        }
+        // a player who died or changed role can still be around until the end of the frame
+        let humans = humans_on_team(
+            ev.team,
+            players
+                .iter()
+                .filter(|(player, _)| player.player_controller != ev.player_controller),
+            delayed_player_spawners.iter(),
+        ) + spawned_this_frame
+            .iter()
+            .filter(|&&team| team == ev.team)
+            .count();
+        // players that do not fit on the team wait in line for a spot
+        if humans >= game_settings.team_size {
+            spawner.insert(Waiting::new(&time));
+        } else {
+            spawned_this_frame.push(ev.team);
+        }
     }
 }
 
-fn get_spritesheet(team: Team, is_queen: bool) -> String {
-    match (team, is_queen) {
-        (Team::Yellow, true) => String::from("spritesheets/queenYellow.png"),
-        (Team::Purple, true) => String::from("spritesheets/queenPurple.png"),
-        (Team::Yellow, false) => String::from("spritesheets/workerYellow.png"),
-        (Team::Purple, false) => String::from("spritesheets/workerPurple.png"),
+/// Half the size of a player's body, set by role rather than by how big the sprite happens to
+/// be drawn
+pub fn body_half_size(has_wings: bool) -> Vec2 {
+    let (width, height) = if has_wings {
+        (QUEEN_RENDER_WIDTH, QUEEN_RENDER_HEIGHT)
+    } else {
+        (WORKER_RENDER_WIDTH, WORKER_RENDER_HEIGHT)
+    };
+    Vec2::new(width / 2.0 * PLAYER_COLLIDER_WIDTH_MULTIPLIER, height / 2.0)
+}
+
+/// The collider players bump into each other and the map with
+pub fn body_collider(has_wings: bool) -> Collider {
+    let half_size = body_half_size(has_wings);
+    Collider::cuboid(half_size.x, half_size.y)
+}
+
+/// Spawns the hitbox and hurtbox sensors that the fights of a player are decided with
+pub fn spawn_combat_boxes(parent: &mut ChildBuilder, role: Role) {
+    for combat_box in CombatBox::ALL {
+        let half_size = role.half_size(combat_box);
+        parent.spawn((
+            combat_box,
+            SpatialBundle::default(),
+            Collider::cuboid(half_size.x, half_size.y),
+            Sensor,
+            // the sensors should not weigh the player down
+            ColliderMassProperties::Density(0.0),
+            ActiveEvents::COLLISION_EVENTS,
+        ));
     }
 }
 
+fn get_spritesheet(team: Team, is_queen: bool) -> &'static str {
+    if is_queen {
+        team.definition().queen_spritesheet
+    } else {
+        team.definition().worker_spritesheet
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_players(
     server: Res<AssetServer>,
     mut atlases: ResMut<Assets<TextureAtlasLayout>>,
     mut commands: Commands,
-    mut delayed_player_spawners: Query<(&mut DelayedPlayerSpawner, Entity)>,
+    mut delayed_player_spawners: Query<
+        (&mut DelayedPlayerSpawner, Entity, Option<&Transform>),
+        Without<Waiting>,
+    >,
+    hives: Query<(&Hive, &Transform)>,
     time: Res<Time>,
+    controls: Res<ControlsConfig>,
+    game_settings: Res<GameSettings>,
 ) {
-    for (mut delayed_player_spawner, entity) in &mut delayed_player_spawners {
+    for (mut delayed_player_spawner, entity, maybe_egg) in &mut delayed_player_spawners {
         delayed_player_spawner.timer.tick(time.delta());
 
         if delayed_player_spawner.timer.finished() {
             commands.entity(entity).despawn();
             let ev = delayed_player_spawner.event;
+            // hatch where the egg was laid, players without a delay never had one
+            let point = match maybe_egg {
+                Some(egg) => egg.translation.truncate(),
+                None => spawn_point(hives.iter(), ev.team, ev.is_queen),
+            };
             let texture: Handle<Image> = server.load(get_spritesheet(ev.team, ev.is_queen));
             let texture_atlas = TextureAtlasLayout::from_grid(
                 Vec2::new(SPRITE_TILE_WIDTH, SPRITE_TILE_HEIGHT),
@@ -724,24 +887,7 @@ fn spawn_players(
             );
             let atlas_handle = atlases.add(texture_atlas);
 
-            let mut input_map = InputMap::default();
-            if let PlayerController::Gamepad(gamepad) = ev.player_controller {
-                input_map.insert(Action::Jump, GamepadButtonType::South);
-                input_map.insert(
-                    Action::Move,
-                    SingleAxis::symmetric(GamepadAxisType::LeftStickX, 0.5),
-                );
-                input_map.insert(Action::Move, VirtualAxis::horizontal_dpad());
-                input_map.insert(Action::Disconnect, GamepadButtonType::Select);
-                if ev.is_queen {
-                    input_map.insert(
-                        Action::Dive,
-                        SingleAxis::negative_only(GamepadAxisType::LeftStickY, -0.9),
-                    );
-                    input_map.insert(Action::Dive, GamepadButtonType::DPadDown);
-                }
-                input_map.set_gamepad(gamepad);
-            }
+            let input_map = input_map(ev.player_controller, ev.is_queen, &controls);
 
             let (player_width, player_height) = if ev.is_queen {
                 (QUEEN_RENDER_WIDTH, QUEEN_RENDER_HEIGHT)
@@ -757,14 +903,7 @@ fn spawn_players(
                         index: SPRITE_IDX_STAND,
                     },
                     transform: Transform {
-                        translation: Vec3::new(
-                            match ev.team {
-                                Team::Yellow => -WINDOW_WIDTH / 20.0,
-                                Team::Purple => WINDOW_WIDTH / 20.0,
-                            },
-                            WINDOW_TOP_Y - (WINDOW_HEIGHT / 9.0),
-                            2.0,
-                        ),
+                        translation: point.extend(2.0),
                         ..Default::default()
                     },
                     sprite: Sprite {
@@ -785,18 +924,12 @@ fn spawn_players(
                 Name::new("Player"),
                 InputManagerBundle::with_map(input_map),
                 ev.player_controller,
-                match ev.team {
-                    Team::Yellow => Direction::Left,
-                    Team::Purple => Direction::Right,
-                },
+                ev.team.side(),
                 ev.team,
                 (
                     RigidBody::Dynamic,
                     GravityScale(PLAYER_GRAVITY_SCALE),
-                    Collider::cuboid(
-                        player_width / 2.0 * PLAYER_COLLIDER_WIDTH_MULTIPLIER,
-                        player_height / 2.0,
-                    ),
+                    body_collider(ev.is_queen),
                     Velocity::default(),
                     ExternalImpulse::default(),
                     LockedAxes::ROTATION_LOCKED,
@@ -808,13 +941,16 @@ fn spawn_players(
                     Ccd::enabled(),
                 ),
             ));
+            player.with_children(|parent| {
+                spawn_combat_boxes(parent, Role::new(ev.is_queen, ev.is_queen));
+            });
             if ev.is_queen {
                 player.insert(Wings);
                 player.insert(Queen);
             }
-            if ev.start_invincible {
+            if ev.start_invincible && game_settings.spawn_invincibility > 0.0 {
                 player.insert(Invincible {
-                    timer: Timer::from_seconds(INVINCIBILITY_DURATION, TimerMode::Once),
+                    timer: Timer::from_seconds(game_settings.spawn_invincibility, TimerMode::Once),
                     animation_timer: Timer::from_seconds(0.1, TimerMode::Repeating),
                 });
             }
@@ -847,7 +983,7 @@ fn handle_invincibility(
     }
 }
 
-fn reset_all_players(
+pub fn reset_all_players(
     players: Query<(Entity, &Player, &Team, Has<Queen>)>,
     mut commands: Commands,
     mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
@@ -0,0 +1,261 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    join::remove_player,
+    player::{
+        add_delayed_player_spawners, reset_all_players, DelayedPlayerSpawner, Killable, Player,
+        PlayerController, SpawnPlayerEvent,
+    },
+    settings::GameSettings,
+    teams::Team,
+    GameState, WinEvent, WINDOW_BOTTOM_Y, WINDOW_HEIGHT,
+};
+
+const QUEUE_FONT_SIZE: f32 = 24.0;
+
+pub struct QueuePlugin;
+
+impl Plugin for QueuePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rotation>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    record_winner.run_if(in_state(GameState::Play)),
+                    admit_waiting_players
+                        .after(add_delayed_player_spawners)
+                        .run_if(in_state(GameState::Join)),
+                    show_waiting_queue,
+                ),
+            )
+            .add_systems(
+                OnExit(GameState::GameOver),
+                rotate_players.before(reset_all_players),
+            );
+    }
+}
+
+/// Who sits out after a game when more players joined than fit on the teams
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RotationPolicy {
+    /// The winning team stays on, and just enough losers make room for the players that waited
+    /// longest
+    #[default]
+    WinnersStay,
+    /// Everyone on the losing teams goes to the back of the line, so the waiting players get in
+    /// first and the losers take whatever spots are left
+    LosersRotate,
+    /// The players that played the most games in a row make room, whichever team won
+    RoundRobin,
+}
+
+impl RotationPolicy {
+    pub const ALL: [RotationPolicy; 3] = [
+        RotationPolicy::WinnersStay,
+        RotationPolicy::LosersRotate,
+        RotationPolicy::RoundRobin,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RotationPolicy::WinnersStay => "winners stay",
+            RotationPolicy::LosersRotate => "losers rotate",
+            RotationPolicy::RoundRobin => "round robin",
+        }
+    }
+}
+
+/// A joined player waiting for a spot on a full team, on a spawner that does not hatch yet
+#[derive(Component)]
+pub struct Waiting {
+    since: f32,
+}
+
+impl Waiting {
+    pub fn new(time: &Time) -> Self {
+        Self {
+            since: time.elapsed_seconds(),
+        }
+    }
+}
+
+#[derive(Default, Resource)]
+struct Rotation {
+    winner: Option<Team>,
+    games_in_a_row: HashMap<PlayerController, u32>,
+}
+
+/// How many humans are playing on a team or about to spawn into it
+pub fn humans_on_team<'a>(
+    team: Team,
+    players: impl Iterator<Item = (&'a Player, &'a Team)>,
+    delayed_player_spawners: impl Iterator<Item = &'a DelayedPlayerSpawner>,
+) -> usize {
+    players
+        .map(|(player, &team)| (player.player_controller, team))
+        .chain(
+            delayed_player_spawners
+                .map(|spawner| (spawner.event.player_controller, spawner.event.team)),
+        )
+        .filter(|&(player_controller, player_team)| {
+            player_team == team && !player_controller.is_bot()
+        })
+        .count()
+}
+
+#[derive(Component)]
+struct QueueText;
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        QueueText,
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: QUEUE_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            ),
+            transform: Transform::from_xyz(0.0, WINDOW_BOTTOM_Y + WINDOW_HEIGHT / 14.0, 100.0),
+            ..Default::default()
+        },
+    ));
+}
+
+fn record_winner(mut ev_win: EventReader<WinEvent>, mut rotation: ResMut<Rotation>) {
+    for win_event in ev_win.read() {
+        rotation.winner.get_or_insert(win_event.team);
+    }
+}
+
+/// Lets the players that waited longest into teams with free spots
+fn admit_waiting_players(
+    mut waiting_players: Query<(Entity, &mut DelayedPlayerSpawner, &Waiting)>,
+    playing_spawners: Query<&DelayedPlayerSpawner, Without<Waiting>>,
+    players: Query<(&Player, &Team)>,
+    mut commands: Commands,
+    game_settings: Res<GameSettings>,
+) {
+    let mut free_spots: Vec<(Team, usize)> = game_settings
+        .teams()
+        .map(|team| {
+            let humans = humans_on_team(team, players.iter(), playing_spawners.iter());
+            (team, game_settings.team_size.saturating_sub(humans))
+        })
+        .collect();
+    let mut waiting: Vec<_> = waiting_players.iter_mut().collect();
+    waiting.sort_by(|(_, _, a), (_, _, b)| a.since.total_cmp(&b.since));
+    for (entity, mut spawner, _) in waiting {
+        // a player that left while waiting is handed to a bot, which has no place in line
+        if spawner.event.player_controller.is_bot() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        // players go to the team they picked if it has room, otherwise to any team that does
+        let own_team = spawner.event.team;
+        let Some((team, spots)) = free_spots
+            .iter_mut()
+            .filter(|(_, spots)| *spots > 0)
+            .min_by_key(|(team, _)| *team != own_team)
+        else {
+            break;
+        };
+        *spots -= 1;
+        if *team != own_team {
+            spawner.event.team = *team;
+            spawner.event.is_queen = false;
+        }
+        commands.entity(entity).remove::<Waiting>();
+    }
+}
+
+/// Benches players after a game so the ones waiting get to play next
+fn rotate_players(
+    players: Query<Killable>,
+    waiting_players: Query<(), With<Waiting>>,
+    mut rotation: ResMut<Rotation>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_settings: Res<GameSettings>,
+    time: Res<Time>,
+) {
+    let winner = rotation.winner.take();
+    let humans: Vec<_> = players
+        .iter()
+        .filter(|(_, _, player, ..)| !player.player_controller.is_bot())
+        .collect();
+    for (_, _, player, ..) in &humans {
+        *rotation
+            .games_in_a_row
+            .entry(player.player_controller)
+            .or_default() += 1;
+    }
+    let mut candidates: Vec<_> = humans
+        .into_iter()
+        .filter(|(_, _, _, &team, ..)| match game_settings.rotation {
+            RotationPolicy::WinnersStay | RotationPolicy::LosersRotate => {
+                winner.map_or(true, |winner| winner != team)
+            }
+            RotationPolicy::RoundRobin => true,
+        })
+        .collect();
+    // whoever played the most games in a row sits out first
+    candidates.sort_by_key(|(_, _, player, ..)| {
+        std::cmp::Reverse(rotation.games_in_a_row[&player.player_controller])
+    });
+    // with nobody waiting everyone just plays again
+    let waiting = waiting_players.iter().count();
+    let benched_count = match game_settings.rotation {
+        RotationPolicy::LosersRotate if waiting > 0 => candidates.len(),
+        _ => waiting,
+    };
+    for benched in candidates.into_iter().take(benched_count) {
+        let (entity, transform, player, &team, has_berry, _, _, maybe_riding_on_ship) = benched;
+        rotation.games_in_a_row.remove(&player.player_controller);
+        remove_player(
+            &mut commands,
+            entity,
+            has_berry,
+            transform,
+            &asset_server,
+            maybe_riding_on_ship,
+        );
+        // back of the line, behind everyone who is already waiting
+        commands.spawn((
+            DelayedPlayerSpawner::new(SpawnPlayerEvent {
+                team,
+                is_queen: false,
+                player_controller: player.player_controller,
+                delay: 0.0,
+                start_invincible: false,
+            }),
+            Waiting::new(&time),
+        ));
+    }
+}
+
+fn show_waiting_queue(
+    mut queue_texts: Query<&mut Text, With<QueueText>>,
+    waiting_players: Query<(&DelayedPlayerSpawner, &Waiting)>,
+) {
+    let mut waiting: Vec<_> = waiting_players.iter().collect();
+    waiting.sort_by(|(_, a), (_, b)| a.since.total_cmp(&b.since));
+    let text = if waiting.is_empty() {
+        String::new()
+    } else {
+        let names: Vec<String> = waiting
+            .iter()
+            .enumerate()
+            .map(|(place, (spawner, _))| {
+                format!("{}. {}", place + 1, spawner.event.player_controller.name())
+            })
+            .collect();
+        format!("Next up: {}", names.join("   "))
+    };
+    for mut queue_text in &mut queue_texts {
+        queue_text.sections[0].value = text.clone();
+    }
+}
@@ -0,0 +1,142 @@
+use bevy::{
+    input::{gamepad::GamepadConnectionEvent, InputSystem},
+    prelude::*,
+};
+
+use crate::{
+    bot::{hand_over_to_bot, hand_over_to_player, BotIds},
+    controls::{input_map, ControlsConfig},
+    join::JoinedGamepads,
+    player::{DelayedPlayerSpawner, Player, PlayerController, Queen},
+};
+
+pub struct ReconnectPlugin;
+
+impl Plugin for ReconnectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OrphanedPlayers>()
+            .add_systems(
+                // runs before joining, so the button press does not also join a new player
+                PreUpdate,
+                reassign_orphaned_players.after(InputSystem),
+            )
+            .add_systems(Update, hand_disconnected_gamepads_to_bots);
+    }
+}
+
+/// Bots keeping the slots of gamepads that disconnected, oldest first
+#[derive(Resource, Default)]
+pub struct OrphanedPlayers(Vec<(Gamepad, PlayerController)>);
+
+impl OrphanedPlayers {
+    /// Bots holding a slot for a gamepad count as the player they stand in for
+    pub fn is_filler_bot(&self, player_controller: PlayerController) -> bool {
+        player_controller.is_bot()
+            && !self
+                .0
+                .iter()
+                .any(|&(_, orphan_controller)| orphan_controller == player_controller)
+    }
+}
+
+fn hand_disconnected_gamepads_to_bots(
+    mut ev_connections: EventReader<GamepadConnectionEvent>,
+    mut commands: Commands,
+    players: Query<(Entity, &Player)>,
+    mut delayed_player_spawners: Query<&mut DelayedPlayerSpawner>,
+    mut joined_gamepads: ResMut<JoinedGamepads>,
+    mut orphaned_players: ResMut<OrphanedPlayers>,
+    mut bot_ids: ResMut<BotIds>,
+) {
+    for ev in ev_connections.read() {
+        if !ev.disconnected() || !joined_gamepads.0.remove(&ev.gamepad) {
+            continue;
+        }
+        let player_controller = PlayerController::Gamepad(ev.gamepad);
+        for (entity, player) in &players {
+            if player.player_controller == player_controller {
+                let bot = hand_over_to_bot(&mut commands, entity, player, &mut bot_ids);
+                orphaned_players.0.push((ev.gamepad, bot));
+            }
+        }
+        // a player waiting to respawn comes back as a bot
+        for mut delayed_player_spawner in &mut delayed_player_spawners {
+            if delayed_player_spawner.event.player_controller == player_controller {
+                let bot = bot_ids.next();
+                delayed_player_spawner.event.player_controller = bot;
+                orphaned_players.0.push((ev.gamepad, bot));
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn reassign_orphaned_players(
+    mut commands: Commands,
+    gamepads: Res<Gamepads>,
+    mut button_inputs: ResMut<ButtonInput<GamepadButton>>,
+    players: Query<(Entity, &Player, Has<Queen>)>,
+    mut delayed_player_spawners: Query<&mut DelayedPlayerSpawner>,
+    mut joined_gamepads: ResMut<JoinedGamepads>,
+    mut orphaned_players: ResMut<OrphanedPlayers>,
+    controls: Res<ControlsConfig>,
+) {
+    // forget slots whose bot has since left the game
+    orphaned_players.0.retain(|&(_, bot)| {
+        players
+            .iter()
+            .any(|(_, player, _)| player.player_controller == bot)
+            || delayed_player_spawners
+                .iter()
+                .any(|spawner| spawner.event.player_controller == bot)
+    });
+
+    for gamepad in gamepads.iter() {
+        if orphaned_players.0.is_empty() {
+            return;
+        }
+        if joined_gamepads.0.contains(&gamepad)
+            || !button_inputs
+                .get_just_pressed()
+                .any(|button| button.gamepad == gamepad)
+        {
+            continue;
+        }
+        // a gamepad that comes back gets its own slot, any other takes the oldest one
+        let index = orphaned_players
+            .0
+            .iter()
+            .position(|&(orphan_gamepad, _)| orphan_gamepad == gamepad)
+            .unwrap_or(0);
+        let (_, bot) = orphaned_players.0.remove(index);
+        let player_controller = PlayerController::Gamepad(gamepad);
+
+        for (entity, player, is_queen) in &players {
+            if player.player_controller == bot {
+                hand_over_to_player(
+                    &mut commands,
+                    entity,
+                    player,
+                    player_controller,
+                    input_map(player_controller, is_queen, &controls),
+                );
+            }
+        }
+        for mut delayed_player_spawner in &mut delayed_player_spawners {
+            if delayed_player_spawner.event.player_controller == bot {
+                delayed_player_spawner.event.player_controller = player_controller;
+            }
+        }
+        joined_gamepads.0.insert(gamepad);
+
+        // the press that reclaimed the slot must not also swap teams when joining
+        let pressed: Vec<_> = button_inputs
+            .get_just_pressed()
+            .filter(|button| button.gamepad == gamepad)
+            .copied()
+            .collect();
+        for button in pressed {
+            button_inputs.clear_just_pressed(button);
+        }
+    }
+}
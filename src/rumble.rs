@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use bevy::{
+    input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest},
+    prelude::*,
+};
+
+use crate::{
+    player::{Player, PlayerController},
+    settings::GameSettings,
+    teams::Team,
+};
+
+pub struct RumblePlugin;
+
+impl Plugin for RumblePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RumbleEvent>()
+            .add_systems(PostUpdate, rumble_gamepads);
+    }
+}
+
+/// Shakes the gamepads of a player or a whole team, other controllers cannot rumble
+#[derive(Event)]
+pub struct RumbleEvent {
+    pub target: RumbleTarget,
+    pub kind: RumbleKind,
+}
+
+pub enum RumbleTarget {
+    Player(PlayerController),
+    Team(Team),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum RumbleKind {
+    Death,
+    BerryDeposited,
+    BecameFighter,
+    BecameSpeedWorker,
+    ShipReachedGoal,
+    ShipAteWorker,
+    QueenLostLife,
+}
+
+impl RumbleKind {
+    fn intensity(&self) -> GamepadRumbleIntensity {
+        match self {
+            RumbleKind::Death => GamepadRumbleIntensity::MAX,
+            RumbleKind::BerryDeposited => GamepadRumbleIntensity::weak_motor(0.4),
+            RumbleKind::BecameFighter => GamepadRumbleIntensity::weak_motor(0.8),
+            RumbleKind::BecameSpeedWorker => GamepadRumbleIntensity::weak_motor(0.6),
+            RumbleKind::ShipReachedGoal => GamepadRumbleIntensity {
+                strong_motor: 0.5,
+                weak_motor: 0.5,
+            },
+            RumbleKind::ShipAteWorker => GamepadRumbleIntensity::strong_motor(0.4),
+            RumbleKind::QueenLostLife => GamepadRumbleIntensity::strong_motor(0.6),
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::from_millis(match self {
+            RumbleKind::Death => 400,
+            RumbleKind::BerryDeposited => 120,
+            RumbleKind::BecameFighter => 250,
+            RumbleKind::BecameSpeedWorker => 200,
+            RumbleKind::ShipReachedGoal => 800,
+            RumbleKind::ShipAteWorker => 300,
+            RumbleKind::QueenLostLife => 500,
+        })
+    }
+}
+
+fn rumble_gamepads(
+    mut ev_rumble: EventReader<RumbleEvent>,
+    mut ev_rumble_requests: EventWriter<GamepadRumbleRequest>,
+    players: Query<(&Player, &Team)>,
+    game_settings: Res<GameSettings>,
+) {
+    for ev in ev_rumble.read() {
+        if game_settings.rumble_intensity <= 0.0 {
+            continue;
+        }
+        let intensity = ev.kind.intensity();
+        let intensity = GamepadRumbleIntensity {
+            strong_motor: intensity.strong_motor * game_settings.rumble_intensity,
+            weak_motor: intensity.weak_motor * game_settings.rumble_intensity,
+        };
+        let gamepads: Vec<Gamepad> = match ev.target {
+            RumbleTarget::Player(PlayerController::Gamepad(gamepad)) => vec![gamepad],
+            RumbleTarget::Player(_) => Vec::new(),
+            RumbleTarget::Team(team) => players
+                .iter()
+                .filter(|(_, &player_team)| player_team == team)
+                .filter_map(|(player, _)| match player.player_controller {
+                    PlayerController::Gamepad(gamepad) => Some(gamepad),
+                    _ => None,
+                })
+                .collect(),
+        };
+        for gamepad in gamepads {
+            ev_rumble_requests.send(GamepadRumbleRequest::Add {
+                gamepad,
+                intensity,
+                duration: ev.kind.duration(),
+            });
+        }
+    }
+}
@@ -1,7 +1,23 @@
-use bevy::{input::common_conditions::input_toggle_active, prelude::*};
+use bevy::{
+    input::common_conditions::input_toggle_active,
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
 use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
 
-use crate::berries::RespawnBerriesEvent;
+use crate::{
+    berries::RespawnBerriesEvent,
+    bot::BotIds,
+    clock::Tiebreak,
+    generator::generate,
+    lobby::ShuffleTeamsEvent,
+    map::{Map, MapLibrary, MapRotation},
+    player::{Queen, SpawnPlayerEvent},
+    queue::RotationPolicy,
+    teams::{Team, TEAMS},
+    weapons::Weapon,
+    GameState,
+};
 
 pub struct SettingsPlugin;
 
@@ -9,9 +25,13 @@ impl Plugin for SettingsPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            show_game_settings.run_if(input_toggle_active(false, KeyCode::Escape)),
+            (
+                show_game_settings.run_if(input_toggle_active(false, KeyCode::Escape)),
+                finish_generating_map,
+            ),
         )
-        .init_resource::<GameSettings>();
+        .init_resource::<GameSettings>()
+        .init_resource::<GeneratingMap>();
     }
 }
 
@@ -20,6 +40,31 @@ pub struct GameSettings {
     pub queen_lives: i32,
     pub ship_speed: f32,
     pub berries_to_win: i32,
+    pub fill_with_bots: bool,
+    /// How many of the teams in `TEAMS` play
+    pub team_count: usize,
+    /// Players per team, bots fill up the rest and extra players wait in line
+    pub team_size: usize,
+    /// Who makes room for the players waiting in line after a game
+    pub rotation: RotationPolicy,
+    /// Scales gamepad rumble, zero turns it off
+    pub rumble_intensity: f32,
+    /// The weapon fighters get from gates that do not pick their own
+    pub fighter_weapon: Weapon,
+    /// Knocks enemies out of the hive a team respawns at
+    pub hive_barrier: bool,
+    /// How long respawned players cannot be killed, zero turns it off
+    pub spawn_invincibility: f32,
+    /// How long a game lasts before the tiebreak decides it, zero means no limit
+    pub match_minutes: f32,
+    pub tiebreak: Tiebreak,
+    /// How long a queen has to stay in a gate to claim it
+    pub gate_capture_time: f32,
+    /// The names of the maps that take turns
+    pub playlist: Vec<String>,
+    pub map_rotation: MapRotation,
+    /// Seeds the map generator, the same seed always lays out the same map
+    pub map_seed: u64,
 }
 
 impl Default for GameSettings {
@@ -28,14 +73,51 @@ impl Default for GameSettings {
             queen_lives: 3,
             ship_speed: 30.0,
             berries_to_win: 6,
+            fill_with_bots: false,
+            team_count: 2,
+            team_size: 5,
+            rotation: RotationPolicy::WinnersStay,
+            rumble_intensity: 0.5,
+            fighter_weapon: Weapon::Sword,
+            hive_barrier: false,
+            spawn_invincibility: 2.0,
+            match_minutes: 0.0,
+            tiebreak: Tiebreak::MostBerries,
+            gate_capture_time: 1.5,
+            playlist: Map::built_in().into_iter().map(|map| map.name).collect(),
+            map_rotation: MapRotation::Sequential,
+            map_seed: 0,
         }
     }
 }
 
+impl GameSettings {
+    /// The teams in play
+    pub fn teams(&self) -> impl Iterator<Item = Team> {
+        Team::all(self.team_count)
+    }
+}
+
+/// A map being generated in the background, as trying seeds can take a while
+#[derive(Resource, Default)]
+struct GeneratingMap {
+    task: Option<Task<Option<Map>>>,
+    seed: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn show_game_settings(
     mut contexts: EguiContexts,
     mut game_settings: ResMut<GameSettings>,
     mut respawn_berries_ev: EventWriter<RespawnBerriesEvent>,
+    mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
+    mut bot_ids: ResMut<BotIds>,
+    queens: Query<&Team, With<Queen>>,
+    mut ev_shuffle_teams: EventWriter<ShuffleTeamsEvent>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    library: Res<MapLibrary>,
+    mut generating: ResMut<GeneratingMap>,
 ) {
     egui::Window::new("Settings").show(contexts.ctx_mut(), |ui| {
         ui.add(egui::Slider::new(&mut game_settings.queen_lives, 1..=15).text("queen lives"));
@@ -48,5 +130,158 @@ fn show_game_settings(
         {
             respawn_berries_ev.send(RespawnBerriesEvent);
         }
+        ui.add(egui::Slider::new(&mut game_settings.rumble_intensity, 0.0..=1.0).text("rumble"));
+        egui::ComboBox::from_label("fighter weapon")
+            .selected_text(format!("{:?}", game_settings.fighter_weapon).to_lowercase())
+            .show_ui(ui, |ui| {
+                for weapon in Weapon::ALL {
+                    ui.selectable_value(
+                        &mut game_settings.fighter_weapon,
+                        weapon,
+                        format!("{:?}", weapon).to_lowercase(),
+                    );
+                }
+            });
+        ui.add(
+            egui::Slider::new(&mut game_settings.spawn_invincibility, 0.0..=5.0)
+                .text("spawn invincibility"),
+        );
+        ui.checkbox(&mut game_settings.hive_barrier, "hive barrier");
+        ui.add(
+            egui::Slider::new(&mut game_settings.gate_capture_time, 0.0..=5.0)
+                .text("gate capture time"),
+        );
+        ui.add(
+            egui::Slider::new(&mut game_settings.match_minutes, 0.0..=15.0)
+                .text("match minutes (0 is no limit)"),
+        );
+        egui::ComboBox::from_label("tiebreak")
+            .selected_text(game_settings.tiebreak.label())
+            .show_ui(ui, |ui| {
+                for tiebreak in Tiebreak::ALL {
+                    ui.selectable_value(&mut game_settings.tiebreak, tiebreak, tiebreak.label());
+                }
+            });
+        // teams can only come and go in the lobby, where the map makes room for them right away
+        if ui
+            .add_enabled(
+                *state.get() == GameState::Join,
+                egui::Slider::new(&mut game_settings.team_count, 2..=TEAMS.len()).text("teams"),
+            )
+            .changed()
+        {
+            respawn_berries_ev.send(RespawnBerriesEvent);
+        }
+        ui.checkbox(&mut game_settings.fill_with_bots, "fill teams with bots");
+        ui.add(egui::Slider::new(&mut game_settings.team_size, 1..=8).text("team size"));
+        egui::ComboBox::from_label("rotation")
+            .selected_text(game_settings.rotation.label())
+            .show_ui(ui, |ui| {
+                for rotation in RotationPolicy::ALL {
+                    ui.selectable_value(&mut game_settings.rotation, rotation, rotation.label());
+                }
+            });
+        ui.horizontal(|ui| {
+            for team in game_settings.teams() {
+                if ui
+                    .button(format!("add {} bot", team.name()).to_lowercase())
+                    .clicked()
+                {
+                    ev_spawn_players.send(SpawnPlayerEvent {
+                        team,
+                        is_queen: !queens.iter().any(|&queen_team| queen_team == team),
+                        player_controller: bot_ids.next(),
+                        delay: 0.0,
+                        start_invincible: false,
+                    });
+                }
+            }
+        });
+        if ui.button("shuffle teams").clicked() {
+            ev_shuffle_teams.send(ShuffleTeamsEvent);
+        }
+        egui::ComboBox::from_label("map rotation")
+            .selected_text(game_settings.map_rotation.label())
+            .show_ui(ui, |ui| {
+                for map_rotation in MapRotation::ALL {
+                    ui.selectable_value(
+                        &mut game_settings.map_rotation,
+                        map_rotation,
+                        map_rotation.label(),
+                    );
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("playlist");
+            for map in &library.maps {
+                let mut on_playlist = game_settings.playlist.contains(&map.name);
+                if ui.checkbox(&mut on_playlist, &map.name).changed() {
+                    if on_playlist {
+                        game_settings.playlist.push(map.name.clone());
+                    } else {
+                        game_settings.playlist.retain(|name| *name != map.name);
+                    }
+                }
+            }
+        });
+        if ui
+            .add_enabled(
+                *state.get() == GameState::Join,
+                egui::Button::new("edit map"),
+            )
+            .clicked()
+        {
+            next_state.set(GameState::Editor);
+        }
+        // generated maps open in the editor, where they can be saved or tried out
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut game_settings.map_seed).prefix("seed "));
+            if generating.task.is_some() {
+                ui.spinner();
+            } else if ui
+                .add_enabled(
+                    *state.get() == GameState::Join,
+                    egui::Button::new("generate map"),
+                )
+                .clicked()
+            {
+                let (seed, berries_to_win) = (game_settings.map_seed, game_settings.berries_to_win);
+                generating.seed = seed;
+                generating.task = Some(
+                    AsyncComputeTaskPool::get()
+                        .spawn(async move { generate(seed, berries_to_win) }),
+                );
+            }
+        });
     });
 }
+
+fn finish_generating_map(
+    mut generating: ResMut<GeneratingMap>,
+    mut game_settings: ResMut<GameSettings>,
+    mut library: ResMut<MapLibrary>,
+    mut map: ResMut<Map>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(task) = &mut generating.task else {
+        return;
+    };
+    let Some(generated) = block_on(future::poll_once(task)) else {
+        return;
+    };
+    generating.task = None;
+    let Some(generated) = generated else {
+        warn!("no playable map turned up for seed {}", generating.seed);
+        return;
+    };
+    if !game_settings.playlist.contains(&generated.name) {
+        game_settings.playlist.push(generated.name.clone());
+    }
+    library.insert(generated.clone());
+    // a game that started while the map was being laid out keeps its own map
+    if *state.get() == GameState::Join {
+        *map = generated;
+        next_state.set(GameState::Editor);
+    }
+}
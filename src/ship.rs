@@ -3,20 +3,26 @@ use bevy_rapier2d::prelude::*;
 use leafwing_input_manager::action_state::ActionState;
 
 use crate::{
+    berries::Berry,
+    join::remove_player,
+    map::Map,
     player::{
-        Action, Direction, KnockBackEvent, Player, Team, Wings, PLAYER_JUMP_IMPULSE,
-        WORKER_RENDER_HEIGHT,
+        Action, Direction, KnockBackEvent, Player, SpawnPlayerEvent, Speed, Wings,
+        PLAYER_JUMP_IMPULSE, RESPAWN_DELAY, WORKER_RENDER_HEIGHT, WORKER_RENDER_WIDTH,
     },
+    rumble::{RumbleEvent, RumbleKind, RumbleTarget},
     settings::GameSettings,
-    GameState, WinCondition, WinEvent, WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_WIDTH,
+    teams::Team,
+    GameState, WinCondition, WinEvent,
 };
 
 pub struct ShipPlugin;
 
 const SHIP_WIDTH: f32 = 124.0 / 2.0;
 const SHIP_HEIGHT: f32 = 67.0 / 2.0;
-const SHIP_WIN_SPOT: f32 = WINDOW_WIDTH / 2.0 - WINDOW_WIDTH / 18.0;
 const SHIP_WIN_SPOT_WIDTH: f32 = 50.0;
+const SPEED_SHIP_MULTIPLIER: f32 = 1.5;
+const EAT_TIME: f32 = 3.0;
 
 impl Plugin for ShipPlugin {
     fn build(&self, app: &mut App) {
@@ -24,25 +30,43 @@ impl Plugin for ShipPlugin {
             .add_systems(
                 Update,
                 (
+                    place_ship_goals,
                     get_on_ship,
                     move_ship,
+                    eat_workers,
                     jump_off_ship,
                     color_ships_with_drivers,
                     check_for_ship_win,
                 ),
             )
-            .add_systems(OnExit(GameState::GameOver), remove_ships);
+            .add_systems(OnExit(GameState::GameOver), remove_ships)
+            .add_systems(OnEnter(GameState::Editor), remove_ships);
     }
 }
 
 #[derive(Component)]
 pub struct Ship;
 
+/// The target a team has to ride the ship to
+#[derive(Component)]
+struct ShipGoal;
+
 #[derive(Component)]
 pub struct RidingOnShip {
     pub ship: Entity,
 }
 
+/// A ship swallowing an enemy of its rider, it stands still until it is done
+#[derive(Component)]
+pub struct Eating {
+    worker: Entity,
+    timer: Timer,
+}
+
+/// A worker stuck in the mouth of a ship, freed if the rider dies or gets off
+#[derive(Component)]
+pub struct BeingEaten;
+
 #[derive(Bundle)]
 struct ShipBundle {
     ship: Ship,
@@ -72,45 +96,94 @@ impl ShipBundle {
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let y = WINDOW_BOTTOM_Y + WINDOW_HEIGHT / 36.0;
-    commands.spawn(ShipBundle::new(0.0, y, &asset_server));
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, map: Res<Map>) {
+    let start = map.ship.start;
+    commands.spawn(ShipBundle::new(start.x, start.y, &asset_server));
+}
+
+/// Marks the goal of every team in play, teams sharing a goal get their targets stacked
+fn place_ship_goals(
+    ship_goals: Query<Entity, With<ShipGoal>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_settings: Res<GameSettings>,
+    map: Res<Map>,
+    mut placed_for: Local<usize>,
+) {
+    if *placed_for == game_settings.team_count && !map.is_changed() {
+        return;
+    }
+    *placed_for = game_settings.team_count;
+    for ship_goal in &ship_goals {
+        commands.entity(ship_goal).despawn();
+    }
     let texture = asset_server.load("ship-target.png");
-    for (sign, team) in [(-1.0, Team::Yellow), (1.0, Team::Purple)] {
-        commands.spawn(SpriteBundle {
-            texture: texture.clone(),
-            sprite: Sprite {
-                custom_size: Some(Vec2::new(SHIP_WIN_SPOT_WIDTH, SHIP_WIN_SPOT_WIDTH)),
-                color: team.color(),
-                ..Default::default()
-            },
-            transform: Transform {
-                translation: Vec3::new(SHIP_WIN_SPOT * sign, y, -1.0),
+    let mut placed_goals = Vec::new();
+    for team in game_settings.teams() {
+        let x = goal_x(&team, &map);
+        let stacked = placed_goals.iter().filter(|&&goal_x| goal_x == x).count();
+        placed_goals.push(x);
+        commands.spawn((
+            ShipGoal,
+            SpriteBundle {
+                texture: texture.clone(),
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(SHIP_WIN_SPOT_WIDTH, SHIP_WIN_SPOT_WIDTH)),
+                    color: team.color(),
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: Vec3::new(
+                        x,
+                        map.ship.start.y + stacked as f32 * SHIP_WIN_SPOT_WIDTH,
+                        -1.0,
+                    ),
+                    ..Default::default()
+                },
                 ..Default::default()
             },
-            ..Default::default()
-        });
+        ));
     }
 }
 
 fn get_on_ship(
     mut collision_events: EventReader<CollisionEvent>,
-    ships: Query<(Option<&Team>, &Transform), With<Ship>>,
-    workers: Query<(&Team, &Transform), (With<Player>, Without<Wings>)>,
+    ships: Query<(Option<&Team>, &Transform, Has<Eating>), With<Ship>>,
+    workers: Query<(&Team, &Transform), (With<Player>, Without<Wings>, Without<BeingEaten>)>,
     mut commands: Commands,
     mut ev_knockback: EventWriter<KnockBackEvent>,
 ) {
+    // a ship can only eat one worker at a time, even if two walk in during the same frame
+    let mut eating_ships = Vec::new();
     for collision_event in collision_events.read() {
         if let CollisionEvent::Started(entity1, entity2, _flags) = collision_event {
             for (ship_entity, player_entity) in [(entity1, entity2), (entity2, entity1)] {
                 if let Ok((worker_team, worker_transform)) = workers.get(*player_entity) {
-                    if let Ok((maybe_ship_team, ship_transform)) = ships.get(*ship_entity) {
+                    if let Ok((maybe_ship_team, ship_transform, is_eating)) =
+                        ships.get(*ship_entity)
+                    {
+                        let in_mouth = maybe_ship_team.is_some_and(|ship_team| {
+                            ship_team != worker_team
+                                && (worker_transform.translation.x - ship_transform.translation.x)
+                                    * ship_direction(ship_team)
+                                    > 0.0
+                        });
                         if maybe_ship_team.is_none() {
                             commands
                                 .entity(*player_entity)
                                 .insert(RigidBody::Fixed)
                                 .insert(RidingOnShip { ship: *ship_entity });
                             commands.entity(*ship_entity).insert(*worker_team);
+                        } else if in_mouth && !is_eating && !eating_ships.contains(ship_entity) {
+                            eating_ships.push(*ship_entity);
+                            commands
+                                .entity(*player_entity)
+                                .insert(RigidBody::Fixed)
+                                .insert(BeingEaten);
+                            commands.entity(*ship_entity).insert(Eating {
+                                worker: *player_entity,
+                                timer: Timer::from_seconds(EAT_TIME, TimerMode::Once),
+                            });
                         } else {
                             let direction =
                                 if worker_transform.translation.x < ship_transform.translation.x {
@@ -131,23 +204,135 @@ fn get_on_ship(
 }
 
 fn move_ship(
-    mut workers_on_ships: Query<(&mut Transform, &RidingOnShip), Without<Ship>>,
-    mut ships: Query<(&Team, &mut Transform), With<Ship>>,
+    mut workers_on_ships: Query<(&mut Transform, &RidingOnShip, Has<Speed>), Without<Ship>>,
+    mut ships: Query<(&Team, &mut Transform, Has<Eating>), With<Ship>>,
     time: Res<Time>,
     game_settings: Res<GameSettings>,
 ) {
-    for (mut worker_transform, riding_on_ship) in workers_on_ships.iter_mut() {
-        let (team, mut ship_transform) = ships.get_mut(riding_on_ship.ship).unwrap();
-        let direction = match team {
-            Team::Yellow => -1.0,
-            Team::Purple => 1.0,
+    for (mut worker_transform, riding_on_ship, is_fast) in workers_on_ships.iter_mut() {
+        // the ship may be gone when the map was just reloaded
+        let Ok((team, mut ship_transform, is_eating)) = ships.get_mut(riding_on_ship.ship) else {
+            continue;
+        };
+        let speed = if is_eating {
+            0.0
+        } else if is_fast {
+            game_settings.ship_speed * SPEED_SHIP_MULTIPLIER
+        } else {
+            game_settings.ship_speed
         };
-        ship_transform.translation.x += direction * game_settings.ship_speed * time.delta_seconds();
+        ship_transform.translation.x += ship_direction(team) * speed * time.delta_seconds();
         worker_transform.translation = ship_transform.translation;
         worker_transform.translation.y += WORKER_RENDER_HEIGHT / 2.0 + SHIP_HEIGHT / 2.0;
     }
 }
 
+/// Ships move towards the goal of their rider's team, with their mouth in front
+fn ship_direction(team: &Team) -> f32 {
+    match team.definition().ship_goal {
+        Direction::Left => -1.0,
+        Direction::Right => 1.0,
+    }
+}
+
+/// Where the ship has to get to for `team` to win
+fn goal_x(team: &Team, map: &Map) -> f32 {
+    match team.definition().ship_goal {
+        Direction::Left => map.ship.left_goal,
+        Direction::Right => map.ship.right_goal,
+    }
+}
+
+/// How far a ship at `x` still has to go to win for `team`
+pub fn distance_to_goal(x: f32, team: &Team, map: &Map) -> f32 {
+    (goal_x(team, map) - x).abs()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn eat_workers(
+    mut ships: Query<(Entity, &Transform, Option<&Team>, &mut Eating), With<Ship>>,
+    mut eaten_workers: Query<
+        (&mut Transform, &mut Sprite, &Player, &Team, Has<Berry>),
+        (With<BeingEaten>, Without<Ship>),
+    >,
+    riders: Query<(&Player, &RidingOnShip), Without<BeingEaten>>,
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut ev_knockback: EventWriter<KnockBackEvent>,
+    mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
+    mut ev_rumble: EventWriter<RumbleEvent>,
+) {
+    for (ship_entity, ship_transform, maybe_rider_team, mut eating) in &mut ships {
+        let Ok((mut transform, mut sprite, player, &team, has_berry)) =
+            eaten_workers.get_mut(eating.worker)
+        else {
+            // the worker was killed by a fighter before the ship finished
+            commands.entity(ship_entity).remove::<Eating>();
+            continue;
+        };
+        let Some(rider_team) = maybe_rider_team else {
+            // the rider died or got off, so the worker gets spat back out
+            commands.entity(ship_entity).remove::<Eating>();
+            commands
+                .entity(eating.worker)
+                .remove::<BeingEaten>()
+                .insert(RigidBody::Dynamic);
+            sprite.custom_size = Some(Vec2::new(WORKER_RENDER_WIDTH, WORKER_RENDER_HEIGHT));
+            ev_knockback.send(KnockBackEvent {
+                entity: eating.worker,
+                direction: if transform.translation.x < ship_transform.translation.x {
+                    Direction::Left
+                } else {
+                    Direction::Right
+                },
+            });
+            continue;
+        };
+
+        eating.timer.tick(time.delta());
+        if eating.timer.finished() {
+            commands.entity(ship_entity).remove::<Eating>();
+            remove_player(
+                &mut commands,
+                eating.worker,
+                has_berry,
+                &transform,
+                &asset_server,
+                None,
+            );
+            ev_spawn_players.send(SpawnPlayerEvent {
+                team,
+                is_queen: false,
+                player_controller: player.player_controller,
+                delay: RESPAWN_DELAY,
+                start_invincible: true,
+            });
+            ev_rumble.send(RumbleEvent {
+                target: RumbleTarget::Player(player.player_controller),
+                kind: RumbleKind::Death,
+            });
+            // the kill goes to whoever is riding the ship
+            for (rider, riding_on_ship) in &riders {
+                if riding_on_ship.ship == ship_entity {
+                    ev_rumble.send(RumbleEvent {
+                        target: RumbleTarget::Player(rider.player_controller),
+                        kind: RumbleKind::ShipAteWorker,
+                    });
+                }
+            }
+        } else {
+            // the worker sinks into the mouth at the front of the ship
+            let height = WORKER_RENDER_HEIGHT * (1.0 - eating.timer.fraction());
+            sprite.custom_size = Some(Vec2::new(WORKER_RENDER_WIDTH, height));
+            transform.translation.x = ship_transform.translation.x
+                + ship_direction(rider_team) * (SHIP_WIDTH + WORKER_RENDER_WIDTH) / 2.0;
+            transform.translation.y =
+                ship_transform.translation.y - SHIP_HEIGHT / 2.0 + height / 2.0;
+        }
+    }
+}
+
 fn jump_off_ship(
     mut query: Query<(
         Entity,
@@ -183,13 +368,24 @@ fn color_ships_with_drivers(
 fn check_for_ship_win(
     mut ships: Query<(&Transform, &Team), With<Ship>>,
     mut ev_win: EventWriter<WinEvent>,
+    mut ev_rumble: EventWriter<RumbleEvent>,
+    state: Res<State<GameState>>,
+    map: Res<Map>,
 ) {
     for (transform, &team) in ships.iter_mut() {
-        if transform.translation.x.abs() > SHIP_WIN_SPOT {
+        // teams only win at their own goal, the ship can be parked at someone else's
+        if (transform.translation.x - goal_x(&team, &map)) * ship_direction(&team) > 0.0 {
             ev_win.send(WinEvent {
                 team,
                 win_condition: WinCondition::Ship,
             });
+            // the ship stays at the goal until the next game, but should only rumble once
+            if *state.get() == GameState::Play {
+                ev_rumble.send(RumbleEvent {
+                    target: RumbleTarget::Team(team),
+                    kind: RumbleKind::ShipReachedGoal,
+                });
+            }
         }
     }
 }
@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use crate::{player::Direction, WINDOW_WIDTH};
+
+/// Everything that sets a team apart, team specific behaviour looks it up here
+pub struct TeamDefinition {
+    pub name: &'static str,
+    pub color: Color,
+    pub queen_spritesheet: &'static str,
+    pub worker_spritesheet: &'static str,
+    pub fighter_spritesheet: &'static str,
+    /// The frame of the gate spritesheets that shows a gate owned by the team
+    pub gate_index: usize,
+    /// The side of the map the team plays from, which picks the join button for it
+    pub side: Direction,
+    /// Where the lives counter of the team sits at the top of the screen
+    pub hud_x: f32,
+    /// The end of the ship track the team rides the ship to
+    pub ship_goal: Direction,
+}
+
+/// Teams join in this order, so a game with fewer teams plays the first ones
+pub const TEAMS: [TeamDefinition; 4] = [
+    TeamDefinition {
+        name: "Yellow",
+        color: Color::rgb(1.0, 0.773, 0.0),
+        queen_spritesheet: "spritesheets/queenYellow.png",
+        worker_spritesheet: "spritesheets/workerYellow.png",
+        fighter_spritesheet: "spritesheets/fighterYellow.png",
+        gate_index: 0,
+        side: Direction::Left,
+        hud_x: -WINDOW_WIDTH / 20.0,
+        ship_goal: Direction::Left,
+    },
+    TeamDefinition {
+        name: "Purple",
+        color: Color::rgb(0.435, 0.0, 1.0),
+        queen_spritesheet: "spritesheets/queenPurple.png",
+        worker_spritesheet: "spritesheets/workerPurple.png",
+        fighter_spritesheet: "spritesheets/fighterPurple.png",
+        gate_index: 1,
+        side: Direction::Right,
+        hud_x: WINDOW_WIDTH / 20.0,
+        ship_goal: Direction::Right,
+    },
+    TeamDefinition {
+        name: "Red",
+        color: Color::rgb(0.902, 0.157, 0.157),
+        queen_spritesheet: "spritesheets/queenRed.png",
+        worker_spritesheet: "spritesheets/workerRed.png",
+        fighter_spritesheet: "spritesheets/fighterRed.png",
+        gate_index: 3,
+        side: Direction::Left,
+        hud_x: -WINDOW_WIDTH / 4.0,
+        ship_goal: Direction::Left,
+    },
+    TeamDefinition {
+        name: "Green",
+        color: Color::rgb(0.235, 0.784, 0.275),
+        queen_spritesheet: "spritesheets/queenGreen.png",
+        worker_spritesheet: "spritesheets/workerGreen.png",
+        fighter_spritesheet: "spritesheets/fighterGreen.png",
+        gate_index: 4,
+        side: Direction::Right,
+        hud_x: WINDOW_WIDTH / 4.0,
+        ship_goal: Direction::Right,
+    },
+];
+
+/// A team in play, by its place in `TEAMS`
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Team(usize);
+
+impl Team {
+    /// The first `count` teams
+    pub fn all(count: usize) -> impl Iterator<Item = Team> {
+        (0..count.min(TEAMS.len())).map(Team)
+    }
+
+    /// The place of the team in `TEAMS`, which is also its place in a map's team layouts
+    pub fn index(&self) -> usize {
+        self.0
+    }
+
+    pub fn definition(&self) -> &'static TeamDefinition {
+        &TEAMS[self.0]
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.definition().name
+    }
+
+    pub fn color(&self) -> Color {
+        self.definition().color
+    }
+
+    pub fn side(&self) -> Direction {
+        self.definition().side
+    }
+
+    /// The team a join button for `side` picks for a player on `current`: the first team on
+    /// that side, or the one after theirs if they are already on it. There is none once the
+    /// player has been through every team on that side.
+    pub fn next_on_side(side: Direction, current: Option<Team>, count: usize) -> Option<Team> {
+        let mut on_side = Team::all(count).filter(|team| team.side() == side);
+        match current {
+            Some(current) if current.side() == side => {
+                on_side.skip_while(|&team| team != current).nth(1)
+            }
+            _ => on_side.next(),
+        }
+    }
+
+    /// The team in the same spot on the other side, the first team on the left mirrors the
+    /// first team on the right and so on
+    pub fn mirrored(&self) -> Option<Team> {
+        let spot = Team::all(TEAMS.len())
+            .filter(|team| team.side() == self.side())
+            .position(|team| team == *self)?;
+        Team::all(TEAMS.len())
+            .filter(|team| team.side() != self.side())
+            .nth(spot)
+    }
+}
@@ -0,0 +1,340 @@
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    utils::HashSet,
+};
+use leafwing_input_manager::{action_state::ActionState, plugin::InputManagerSystem};
+
+use crate::{
+    bot::BotIds,
+    join::{cancel_delayed_spawn, join_team, joined_team},
+    lobby::SwapTeamEvent,
+    player::{
+        press_with_value, Action, DelayedPlayerSpawner, Direction, PlayerController, Queen,
+        SpawnPlayerEvent,
+    },
+    settings::GameSettings,
+    teams::{Team, TEAMS},
+    GameState, WINDOW_BOTTOM_Y, WINDOW_LEFT_X,
+};
+
+/// One pad on each half of the screen, so two people can share a tablet
+const TOUCH_PADS: [u8; 2] = [0, 1];
+const JOYSTICK_RADIUS: f32 = 110.0;
+const JOYSTICK_KNOB_RADIUS: f32 = 45.0;
+const JOYSTICK_DEADZONE: f32 = 0.2;
+const BUTTON_RADIUS: f32 = 55.0;
+const LOBBY_BUTTON_RADIUS: f32 = 40.0;
+const LABEL_FONT_SIZE: f32 = 24.0;
+/// Touches a bit outside a control still count, as fingers are not precise
+const TOUCH_SLACK: f32 = 1.3;
+const OVERLAY_ALPHA: f32 = 0.3;
+const OVERLAY_Z: f32 = 50.0;
+
+pub struct TouchPlugin;
+
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>()
+            .init_resource::<JoinedTouchPads>()
+            .add_systems(Update, enable_touch_controls)
+            .add_systems(
+                PreUpdate,
+                handle_touches
+                    .after(InputManagerSystem::Update)
+                    .run_if(|touch_controls: Res<TouchControls>| touch_controls.enabled),
+            );
+    }
+}
+
+/// The overlay stays hidden until the first touch, so it never gets in the way on desktop
+#[derive(Resource, Default)]
+pub struct TouchControls {
+    pub enabled: bool,
+}
+
+#[derive(Resource, Default)]
+pub struct JoinedTouchPads(pub HashSet<u8>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TouchControlKind {
+    Joystick,
+    Knob,
+    Jump,
+    Dive,
+    Attack,
+    JoinYellow,
+    JoinPurple,
+    Ready,
+    Queen,
+}
+
+#[derive(Component)]
+struct TouchControl {
+    pad: u8,
+    kind: TouchControlKind,
+}
+
+impl TouchControlKind {
+    const ALL: [TouchControlKind; 9] = [
+        TouchControlKind::Joystick,
+        TouchControlKind::Knob,
+        TouchControlKind::Jump,
+        TouchControlKind::Dive,
+        TouchControlKind::Attack,
+        TouchControlKind::JoinYellow,
+        TouchControlKind::JoinPurple,
+        TouchControlKind::Ready,
+        TouchControlKind::Queen,
+    ];
+
+    /// Where the control sits on the left pad, the right pad is mirrored
+    fn left_pad_position(&self) -> Vec2 {
+        match self {
+            TouchControlKind::Joystick | TouchControlKind::Knob => {
+                Vec2::new(WINDOW_LEFT_X + 170.0, WINDOW_BOTTOM_Y + 170.0)
+            }
+            TouchControlKind::Jump => Vec2::new(WINDOW_LEFT_X + 430.0, WINDOW_BOTTOM_Y + 100.0),
+            TouchControlKind::Dive => Vec2::new(WINDOW_LEFT_X + 400.0, WINDOW_BOTTOM_Y + 240.0),
+            TouchControlKind::Attack => Vec2::new(WINDOW_LEFT_X + 560.0, WINDOW_BOTTOM_Y + 170.0),
+            TouchControlKind::JoinYellow => {
+                Vec2::new(WINDOW_LEFT_X + 90.0, WINDOW_BOTTOM_Y + 380.0)
+            }
+            TouchControlKind::JoinPurple => {
+                Vec2::new(WINDOW_LEFT_X + 200.0, WINDOW_BOTTOM_Y + 380.0)
+            }
+            TouchControlKind::Ready => Vec2::new(WINDOW_LEFT_X + 310.0, WINDOW_BOTTOM_Y + 380.0),
+            TouchControlKind::Queen => Vec2::new(WINDOW_LEFT_X + 420.0, WINDOW_BOTTOM_Y + 380.0),
+        }
+    }
+
+    fn position(&self, pad: u8) -> Vec2 {
+        let position = self.left_pad_position();
+        if pad == 0 {
+            position
+        } else {
+            Vec2::new(-position.x, position.y)
+        }
+    }
+
+    fn radius(&self) -> f32 {
+        match self {
+            TouchControlKind::Joystick => JOYSTICK_RADIUS,
+            TouchControlKind::Knob => JOYSTICK_KNOB_RADIUS,
+            TouchControlKind::Jump | TouchControlKind::Dive | TouchControlKind::Attack => {
+                BUTTON_RADIUS
+            }
+            TouchControlKind::JoinYellow
+            | TouchControlKind::JoinPurple
+            | TouchControlKind::Ready
+            | TouchControlKind::Queen => LOBBY_BUTTON_RADIUS,
+        }
+    }
+
+    fn color(&self) -> Color {
+        // join buttons take the colour of the first team on their side
+        let side_color = |side| {
+            Team::next_on_side(side, None, TEAMS.len()).map_or(Color::WHITE, |team| team.color())
+        };
+        match self {
+            TouchControlKind::JoinYellow => side_color(Direction::Left),
+            TouchControlKind::JoinPurple => side_color(Direction::Right),
+            _ => Color::WHITE,
+        }
+        .with_a(OVERLAY_ALPHA)
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        match self {
+            TouchControlKind::Jump => Some("jump"),
+            TouchControlKind::Dive => Some("dive"),
+            TouchControlKind::Attack => Some("attack"),
+            TouchControlKind::Ready => Some("ready"),
+            TouchControlKind::Queen => Some("queen"),
+            _ => None,
+        }
+    }
+
+    fn contains(&self, pad: u8, point: Vec2) -> bool {
+        point.distance(self.position(pad)) <= self.radius() * TOUCH_SLACK
+    }
+}
+
+fn enable_touch_controls(
+    touches: Res<Touches>,
+    mut touch_controls: ResMut<TouchControls>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    if touch_controls.enabled || touches.iter().next().is_none() {
+        return;
+    }
+    touch_controls.enabled = true;
+    for pad in TOUCH_PADS {
+        for kind in TouchControlKind::ALL {
+            let position = kind.position(pad);
+            // the knob is drawn above the joystick it moves around in
+            let z = if kind == TouchControlKind::Knob {
+                OVERLAY_Z + 1.0
+            } else {
+                OVERLAY_Z
+            };
+            let mut control = commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: Mesh2dHandle(meshes.add(Circle::new(kind.radius()))),
+                    material: materials.add(kind.color()),
+                    transform: Transform::from_translation(position.extend(z)),
+                    ..Default::default()
+                },
+                TouchControl { pad, kind },
+            ));
+            if let Some(label) = kind.label() {
+                control.with_children(|parent| {
+                    parent.spawn(Text2dBundle {
+                        text: Text::from_section(
+                            label,
+                            TextStyle {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                font_size: LABEL_FONT_SIZE,
+                                color: Color::WHITE.with_a(OVERLAY_ALPHA * 2.0),
+                            },
+                        ),
+                        transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                        ..Default::default()
+                    });
+                });
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_touches(
+    touches: Res<Touches>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut controls: Query<(&TouchControl, &mut Transform)>,
+    mut action_states: Query<(
+        &mut ActionState<Action>,
+        &PlayerController,
+        &Team,
+        Has<Queen>,
+    )>,
+    mut delayed_player_spawners: Query<(Entity, &mut DelayedPlayerSpawner)>,
+    mut ev_spawn_players: EventWriter<SpawnPlayerEvent>,
+    mut ev_swap_team: EventWriter<SwapTeamEvent>,
+    mut joined_touch_pads: ResMut<JoinedTouchPads>,
+    mut bot_ids: ResMut<BotIds>,
+    state: Res<State<GameState>>,
+    game_settings: Res<GameSettings>,
+    mut commands: Commands,
+) {
+    let Ok((camera, camera_transform)) = cameras.get_single() else {
+        return;
+    };
+    let to_world = |position: Vec2| camera.viewport_to_world_2d(camera_transform, position);
+
+    for pad in TOUCH_PADS {
+        let player_controller = PlayerController::Touch { pad };
+        let mut pressed_actions = Vec::new();
+
+        // the joystick follows the touch that started on it, even after sliding off
+        let joystick_center = TouchControlKind::Joystick.position(pad);
+        let joystick_offset = touches
+            .iter()
+            .filter(|touch| {
+                to_world(touch.start_position())
+                    .is_some_and(|start| TouchControlKind::Joystick.contains(pad, start))
+            })
+            .find_map(|touch| to_world(touch.position()))
+            .map(|position| (position - joystick_center).clamp_length_max(JOYSTICK_RADIUS));
+        if let Some(offset) = joystick_offset {
+            let value = offset.x / JOYSTICK_RADIUS;
+            if value.abs() > JOYSTICK_DEADZONE {
+                pressed_actions.push((Action::Move, value));
+            }
+        }
+        for (control, mut transform) in &mut controls {
+            if control.pad == pad && control.kind == TouchControlKind::Knob {
+                let knob_position = joystick_center + joystick_offset.unwrap_or_default();
+                transform.translation = knob_position.extend(transform.translation.z);
+            }
+        }
+
+        for touch in touches.iter() {
+            let Some(position) = to_world(touch.position()) else {
+                continue;
+            };
+            if TouchControlKind::Dive.contains(pad, position) {
+                pressed_actions.push((Action::Dive, 1.0));
+            }
+        }
+
+        for touch in touches.iter_just_pressed() {
+            let Some(position) = to_world(touch.position()) else {
+                continue;
+            };
+            for (kind, action) in [
+                (TouchControlKind::Jump, Action::Jump),
+                (TouchControlKind::Attack, Action::Attack),
+                (TouchControlKind::Attack, Action::Throw),
+                (TouchControlKind::Ready, Action::Ready),
+                (TouchControlKind::Queen, Action::ToggleQueen),
+            ] {
+                if kind.contains(pad, position) {
+                    pressed_actions.push((action, 1.0));
+                }
+            }
+            let side = if TouchControlKind::JoinYellow.contains(pad, position) {
+                Direction::Left
+            } else if TouchControlKind::JoinPurple.contains(pad, position) {
+                Direction::Right
+            } else {
+                continue;
+            };
+            let joined_team = joined_team(
+                player_controller,
+                action_states
+                    .iter()
+                    .map(|(_, &controller, &team, _)| (controller, team)),
+                &delayed_player_spawners,
+            );
+            let already_joined = joined_touch_pads.0.contains(&pad);
+            let next_team = Team::next_on_side(side, joined_team, game_settings.team_count);
+            // the join button of your own side goes round its teams and leaves the game after
+            // the last one
+            if already_joined && (joined_team.is_none() || next_team.is_none()) {
+                joined_touch_pads.0.remove(&pad);
+                pressed_actions.push((Action::Disconnect, 1.0));
+                cancel_delayed_spawn(
+                    &mut commands,
+                    &mut delayed_player_spawners,
+                    player_controller,
+                    state.get(),
+                    &mut bot_ids,
+                );
+            } else if let Some(team) = next_team {
+                join_team(
+                    player_controller,
+                    team,
+                    already_joined,
+                    &mut ev_spawn_players,
+                    &mut ev_swap_team,
+                );
+                joined_touch_pads.0.insert(pad);
+            }
+        }
+
+        for (mut action_state, &controller, _, is_queen) in &mut action_states {
+            if controller == player_controller {
+                for (action, value) in &pressed_actions {
+                    // only queens can dive, just like with the other controllers
+                    if *action != Action::Dive || is_queen {
+                        press_with_value(&mut action_state, action, *value);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,743 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::{
+    berries::{berry_cell_positions, BERRY_RENDER_RADIUS},
+    gates::{GATE_HEIGHT, GATE_WIDTH},
+    hive::HiveRole,
+    map::{Map, MapLibrary},
+    player::{
+        PLAYER_COLLIDER_WIDTH_MULTIPLIER, PLAYER_FRICTION_AIR, PLAYER_FRICTION_GROUND,
+        PLAYER_GRAVITY_SCALE, PLAYER_JUMP_IMPULSE, PLAYER_MAX_FALL_SPEED, PLAYER_MAX_VELOCITY_X,
+        PLAYER_MOVEMENT_IMPULSE_AIR, PLAYER_MOVEMENT_IMPULSE_GROUND, WORKER_RENDER_HEIGHT,
+        WORKER_RENDER_WIDTH,
+    },
+    settings::GameSettings,
+    teams::{Team, TEAMS},
+    PIXELS_PER_METER, WINDOW_BOTTOM_Y, WINDOW_HEIGHT, WINDOW_LEFT_X, WINDOW_RIGHT_X, WINDOW_TOP_Y,
+    WINDOW_WIDTH,
+};
+
+/// The gravity bevy_rapier pulls 2D bodies down with before their gravity scale
+const RAPIER_GRAVITY: f32 = 98.1;
+/// Rapier steps at most this far at a time
+const TIME_STEP: f32 = 1.0 / 60.0;
+/// Workers still in the air after this long are taken to never land
+const MAX_FLIGHT_TIME: f32 = 3.0;
+/// How far apart along a ledge workers try jumping from
+const LAUNCH_SPACING: f32 = 16.0;
+/// How much longer a team can take to bring a berry home than the quickest team
+const FAIRNESS_TOLERANCE: f32 = 0.1;
+/// How far apart two pieces can be and still mirror each other
+const MIRROR_TOLERANCE: f32 = 1.0;
+/// Keeps a worker standing on a platform from touching it
+const STANDING_GAP: f32 = 0.01;
+
+/// Something that makes a map unplayable or unfair
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    UnreachableBerries {
+        position: Vec2,
+        teams: Vec<Team>,
+    },
+    UnreachableGate {
+        position: Vec2,
+        teams: Vec<Team>,
+    },
+    UnreachableShip {
+        teams: Vec<Team>,
+    },
+    UnreachableCells {
+        team: Team,
+    },
+    /// No worker can get onto this part of a platform
+    UnreachableLedge {
+        position: Vec2,
+    },
+    /// Workers that get onto this ledge cannot get back to their berry cells
+    SoftLock {
+        position: Vec2,
+        teams: Vec<Team>,
+    },
+    /// The team takes longer on average to bring a berry home than the quickest team
+    Unfair {
+        team: Team,
+        seconds: f32,
+        quickest: Team,
+        quickest_seconds: f32,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::UnreachableBerries { position, teams } => write!(
+                f,
+                "berries at {} are out of reach for {} workers",
+                describe(*position),
+                team_names(teams)
+            ),
+            Problem::UnreachableGate { position, teams } => write!(
+                f,
+                "gate at {} is out of reach for {} workers",
+                describe(*position),
+                team_names(teams)
+            ),
+            Problem::UnreachableShip { teams } => write!(
+                f,
+                "the ship is out of reach for {} workers",
+                team_names(teams)
+            ),
+            Problem::UnreachableCells { team } => {
+                write!(f, "{} workers cannot reach their berry cells", team.name())
+            }
+            Problem::UnreachableLedge { position } => write!(
+                f,
+                "no worker can get onto the ledge at {}",
+                describe(*position)
+            ),
+            Problem::SoftLock { position, teams } => write!(
+                f,
+                "{} workers on the ledge at {} cannot get back to their berry cells",
+                team_names(teams),
+                describe(*position)
+            ),
+            Problem::Unfair {
+                team,
+                seconds,
+                quickest,
+                quickest_seconds,
+            } => write!(
+                f,
+                "{} workers take {seconds:.1}s on average to bring a berry home, {} workers \
+                 {quickest_seconds:.1}s",
+                team.name(),
+                quickest.name()
+            ),
+        }
+    }
+}
+
+fn describe(position: Vec2) -> String {
+    format!("({:.0}, {:.0})", position.x, position.y)
+}
+
+fn team_names(teams: &[Team]) -> String {
+    let names: Vec<&str> = teams.iter().map(|team| team.name()).collect();
+    names.join(", ")
+}
+
+/// How a worker moves, in pixels and seconds. The game hands its impulses to Rapier, which
+/// weighs the worker's collider at a density of one per square metre.
+struct Movement {
+    half_size: Vec2,
+    jump_speed: f32,
+    gravity: f32,
+    max_fall_speed: f32,
+    air_acceleration: f32,
+    /// How much of its sideways speed a worker loses each second in the air
+    air_friction: f32,
+    max_speed: f32,
+    /// How fast workers run once friction holds them back as much as they push
+    run_speed: f32,
+}
+
+impl Movement {
+    fn worker() -> Self {
+        let size = Vec2::new(
+            WORKER_RENDER_WIDTH * PLAYER_COLLIDER_WIDTH_MULTIPLIER,
+            WORKER_RENDER_HEIGHT,
+        );
+        let mass = size.x * size.y / (PIXELS_PER_METER * PIXELS_PER_METER);
+        let run_speed = PLAYER_MOVEMENT_IMPULSE_GROUND / PLAYER_FRICTION_GROUND;
+        Self {
+            half_size: size / 2.0,
+            jump_speed: PLAYER_JUMP_IMPULSE / mass,
+            gravity: RAPIER_GRAVITY * PLAYER_GRAVITY_SCALE,
+            max_fall_speed: PLAYER_MAX_FALL_SPEED,
+            air_acceleration: PLAYER_MOVEMENT_IMPULSE_AIR / mass,
+            air_friction: PLAYER_FRICTION_AIR / mass,
+            max_speed: PLAYER_MAX_VELOCITY_X,
+            run_speed: run_speed.min(PLAYER_MAX_VELOCITY_X),
+        }
+    }
+
+    fn body(&self, position: Vec2) -> Rect {
+        Rect::from_center_half_size(position, self.half_size)
+    }
+}
+
+/// A stretch along the top of a platform where a worker can stand
+#[derive(Clone, Copy, Debug)]
+struct Ledge {
+    platform: usize,
+    /// How far left the middle of a worker standing here can be
+    left: f32,
+    right: f32,
+    /// The top of the platform
+    y: f32,
+}
+
+impl Ledge {
+    fn middle(&self) -> Vec2 {
+        Vec2::new((self.left + self.right) / 2.0, self.y)
+    }
+
+    /// Where a worker standing here at `x` is
+    fn standing_at(&self, x: f32, movement: &Movement) -> Vec2 {
+        Vec2::new(x, self.y + movement.half_size.y + STANDING_GAP)
+    }
+}
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    a.min.x < b.max.x && a.max.x > b.min.x && a.min.y < b.max.y && a.max.y > b.min.y
+}
+
+/// The parts of `left..right` outside `cut_left..cut_right`
+fn cut(left: f32, right: f32, cut_left: f32, cut_right: f32) -> Vec<(f32, f32)> {
+    let mut parts = Vec::new();
+    if left < cut_left {
+        parts.push((left, right.min(cut_left)));
+    }
+    if right > cut_right {
+        parts.push((left.max(cut_right), right));
+    }
+    parts
+}
+
+/// The platforms of a map, the ledges on them and how long workers take to get between them
+struct Arena {
+    movement: Movement,
+    platforms: Vec<Rect>,
+    ledges: Vec<Ledge>,
+    /// What workers need to touch, such as berries and berry cells
+    targets: Vec<Rect>,
+    /// The quickest way from one ledge straight to another, in seconds
+    hops: Vec<Vec<f32>>,
+    /// Which targets a worker can touch from each ledge, standing there or on the way to
+    /// another ledge
+    touches: Vec<Vec<bool>>,
+}
+
+impl Arena {
+    fn new(map: &Map, targets: Vec<Rect>) -> Self {
+        let movement = Movement::worker();
+        let platforms: Vec<Rect> = map
+            .platforms
+            .iter()
+            .map(|platform| Rect::from_center_size(platform.position, platform.size))
+            .collect();
+        let height = movement.half_size.y * 2.0;
+        let mut ledges = Vec::new();
+        for (index, platform) in platforms.iter().enumerate() {
+            let y = platform.max.y;
+            // workers on the ceiling would be off the screen
+            if y + height > WINDOW_TOP_Y {
+                continue;
+            }
+            let mut free = vec![(platform.min.x, platform.max.x)];
+            for other in platforms
+                .iter()
+                .filter(|other| other.min.y < y + height && other.max.y > y)
+            {
+                free = free
+                    .into_iter()
+                    .flat_map(|(left, right)| {
+                        cut(
+                            left,
+                            right,
+                            other.min.x - movement.half_size.x,
+                            other.max.x + movement.half_size.x,
+                        )
+                    })
+                    .collect();
+            }
+            ledges.extend(free.into_iter().map(|(left, right)| Ledge {
+                platform: index,
+                left,
+                right,
+                y,
+            }));
+        }
+        let mut arena = Self {
+            movement,
+            platforms,
+            ledges,
+            targets,
+            hops: Vec::new(),
+            touches: Vec::new(),
+        };
+        for from in 0..arena.ledges.len() {
+            let mut hops = vec![f32::INFINITY; arena.ledges.len()];
+            let mut touched: Vec<bool> = arena
+                .targets
+                .iter()
+                .map(|&target| overlaps(arena.standing_area(from), target))
+                .collect();
+            for (to, time) in arena.moves(from, &mut touched) {
+                hops[to] = hops[to].min(time);
+            }
+            arena.hops.push(hops);
+            arena.touches.push(touched);
+        }
+        arena
+    }
+
+    /// Everything a worker walking along a ledge overlaps
+    fn standing_area(&self, ledge: usize) -> Rect {
+        let ledge = self.ledges[ledge];
+        Rect::new(
+            ledge.left - self.movement.half_size.x,
+            ledge.y,
+            ledge.right + self.movement.half_size.x,
+            ledge.y + self.movement.half_size.y * 2.0,
+        )
+    }
+
+    fn hit(&self, position: Vec2) -> Option<usize> {
+        let body = self.movement.body(position);
+        self.platforms
+            .iter()
+            .position(|&platform| overlaps(body, platform))
+    }
+
+    /// The ledge of a platform closest to where a worker landed on it
+    fn ledge_at(&self, platform: usize, x: f32) -> Option<usize> {
+        let distance = |ledge: &Ledge| (ledge.left - x).max(x - ledge.right).max(0.0);
+        (0..self.ledges.len())
+            .filter(|&index| self.ledges[index].platform == platform)
+            .min_by(|&a, &b| distance(&self.ledges[a]).total_cmp(&distance(&self.ledges[b])))
+    }
+
+    /// Follows a worker through the air, holding the stick one way the whole time, to the
+    /// ledge it lands on and how long that takes. Marks the targets it touches on the way.
+    fn fly(
+        &self,
+        mut position: Vec2,
+        mut velocity: Vec2,
+        input: f32,
+        touched: &mut [bool],
+    ) -> Option<(usize, f32)> {
+        let movement = &self.movement;
+        let mut time = 0.0;
+        while time < MAX_FLIGHT_TIME {
+            time += TIME_STEP;
+            velocity.x += (input * movement.air_acceleration - velocity.x * movement.air_friction)
+                * TIME_STEP;
+            velocity.x = velocity.x.clamp(-movement.max_speed, movement.max_speed);
+            velocity.y = (velocity.y - movement.gravity * TIME_STEP).max(-movement.max_fall_speed);
+
+            position.x += velocity.x * TIME_STEP;
+            if let Some(platform) = self.hit(position) {
+                let platform = self.platforms[platform];
+                position.x = if velocity.x > 0.0 {
+                    platform.min.x - movement.half_size.x
+                } else {
+                    platform.max.x + movement.half_size.x
+                };
+                velocity.x = 0.0;
+            }
+            position.y += velocity.y * TIME_STEP;
+            if let Some(platform) = self.hit(position) {
+                if velocity.y <= 0.0 {
+                    return self
+                        .ledge_at(platform, position.x)
+                        .map(|ledge| (ledge, time));
+                }
+                position.y = self.platforms[platform].min.y - movement.half_size.y;
+                velocity.y = 0.0;
+            }
+            let body = movement.body(position);
+            for (touched, &target) in touched.iter_mut().zip(&self.targets) {
+                *touched |= overlaps(body, target);
+            }
+
+            // the screen wraps around where there is no wall
+            if position.x > WINDOW_RIGHT_X {
+                position.x -= WINDOW_WIDTH;
+            } else if position.x < WINDOW_LEFT_X {
+                position.x += WINDOW_WIDTH;
+            }
+            if position.y < WINDOW_BOTTOM_Y {
+                position.y += WINDOW_HEIGHT;
+            } else if position.y > WINDOW_TOP_Y {
+                position.y -= WINDOW_HEIGHT;
+            }
+        }
+        None
+    }
+
+    /// Every ledge a worker can jump or walk to from a ledge, with how long it takes from the
+    /// middle of the ledge
+    fn moves(&self, from: usize, touched: &mut [bool]) -> Vec<(usize, f32)> {
+        let ledge = self.ledges[from];
+        let run_speed = self.movement.run_speed;
+        let mut moves = Vec::new();
+        let launches = ((ledge.right - ledge.left) / LAUNCH_SPACING).ceil() as usize;
+        for launch in 0..=launches {
+            let x = (ledge.left + launch as f32 * LAUNCH_SPACING).min(ledge.right);
+            let walk_time = (x - ledge.middle().x).abs() / run_speed;
+            for speed in [-run_speed, 0.0, run_speed] {
+                for input in [-1.0, 0.0, 1.0] {
+                    let velocity = Vec2::new(speed, self.movement.jump_speed);
+                    let position = ledge.standing_at(x, &self.movement);
+                    if let Some((to, time)) = self.fly(position, velocity, input, touched) {
+                        moves.push((to, walk_time + time));
+                    }
+                }
+            }
+        }
+        // running off either end, unless a wall is in the way
+        let edges = [
+            (ledge.left - self.movement.half_size.x, -run_speed),
+            (ledge.right + self.movement.half_size.x, run_speed),
+        ];
+        for (x, speed) in edges {
+            let position = ledge.standing_at(x, &self.movement);
+            if self.hit(position).is_some() {
+                continue;
+            }
+            let walk_time = (x - ledge.middle().x).abs() / run_speed;
+            for input in [-1.0, 0.0, 1.0] {
+                let velocity = Vec2::new(speed, 0.0);
+                if let Some((to, time)) = self.fly(position, velocity, input, touched) {
+                    moves.push((to, walk_time + time));
+                }
+            }
+        }
+        moves.retain(|&(to, _)| to != from);
+        moves
+    }
+
+    /// How long it takes to get to each ledge from the quickest of `starts`
+    fn travel_times(&self, starts: &[(usize, f32)]) -> Vec<f32> {
+        let mut times = vec![f32::INFINITY; self.ledges.len()];
+        for &(ledge, time) in starts {
+            times[ledge] = times[ledge].min(time);
+        }
+        let mut visited = vec![false; self.ledges.len()];
+        while let Some(current) = (0..self.ledges.len())
+            .filter(|&ledge| !visited[ledge] && times[ledge].is_finite())
+            .min_by(|&a, &b| times[a].total_cmp(&times[b]))
+        {
+            visited[current] = true;
+            for (next, hop) in self.hops[current].iter().enumerate() {
+                times[next] = times[next].min(times[current] + hop);
+            }
+        }
+        times
+    }
+
+    /// Where the workers of a team land after hatching
+    fn spawns(&self, map: &Map, team: Team) -> Vec<(usize, f32)> {
+        let mut touched = vec![false; self.targets.len()];
+        map.team(team)
+            .hives
+            .iter()
+            .filter(|hive| hive.role != HiveRole::Queens)
+            .flat_map(|hive| {
+                [-1.0, 0.0, 1.0]
+                    .map(|input| self.fly(hive.position, Vec2::ZERO, input, &mut touched))
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// The ledges a worker can touch a target from
+    fn ledges_touching(&self, target: usize) -> Vec<usize> {
+        (0..self.ledges.len())
+            .filter(|&ledge| self.touches[ledge][target])
+            .collect()
+    }
+
+    /// The ledges a worker stands in an area on
+    fn ledges_in(&self, area: Rect) -> Vec<usize> {
+        (0..self.ledges.len())
+            .filter(|&ledge| overlaps(self.standing_area(ledge), area))
+            .collect()
+    }
+}
+
+/// Whether every piece on one side has its mirror image on the other
+fn is_mirrored(map: &Map) -> bool {
+    let mirrors = |a: Vec2, b: Vec2| a.distance(Vec2::new(-b.x, b.y)) < MIRROR_TOLERANCE;
+    let platforms = map.platforms.iter().all(|platform| {
+        map.platforms.iter().any(|other| {
+            mirrors(platform.position, other.position)
+                && platform.size.distance(other.size) < MIRROR_TOLERANCE
+        })
+    });
+    let gates = map.gates.iter().all(|gate| {
+        map.gates.iter().any(|other| {
+            mirrors(gate.position, other.position)
+                && gate.kind == other.kind
+                && gate.weapon == other.weapon
+        })
+    });
+    let berry_bunches = map
+        .berry_bunches
+        .iter()
+        .all(|&bunch| map.berry_bunches.iter().any(|&other| mirrors(bunch, other)));
+    let ship = map.ship.start.x.abs() < MIRROR_TOLERANCE
+        && (map.ship.left_goal + map.ship.right_goal).abs() < MIRROR_TOLERANCE;
+    let teams = Team::all(TEAMS.len()).all(|team| {
+        let Some(other_team) = team.mirrored() else {
+            return true;
+        };
+        let (layout, other) = (map.team(team), map.team(other_team));
+        mirrors(layout.join_gate, other.join_gate)
+            && mirrors(layout.berry_cells.position, other.berry_cells.position)
+            && layout.berry_cells.rows == other.berry_cells.rows
+            && layout.hives.len() == other.hives.len()
+            && layout.hives.iter().all(|hive| {
+                other
+                    .hives
+                    .iter()
+                    .any(|other| mirrors(hive.position, other.position) && hive.role == other.role)
+            })
+    });
+    platforms && gates && berry_bunches && ship && teams
+}
+
+/// Plays every jump a worker can make on the map and reports what workers cannot get to,
+/// where they get stuck, and whether a lopsided map favours a team. Checks as many berry cells
+/// as a team needs to fill to win.
+pub fn validate(map: &Map, berries_to_win: i32) -> Vec<Problem> {
+    let teams: Vec<Team> = Team::all(TEAMS.len()).collect();
+    // everything workers have to touch: the bunches, then the cells of each team, then the ship
+    let mut targets: Vec<Rect> = map
+        .berry_bunches
+        .iter()
+        .map(|&bunch| Rect::from_center_half_size(bunch, Vec2::splat(BERRY_RENDER_RADIUS)))
+        .collect();
+    let cell_targets: Vec<Vec<usize>> =
+        teams
+            .iter()
+            .map(|&team| {
+                let cells = berry_cell_positions(team, &map.team(team).berry_cells, berries_to_win);
+                let first = targets.len();
+                targets.extend(cells.into_iter().map(|cell| {
+                    Rect::from_center_half_size(cell, Vec2::splat(BERRY_RENDER_RADIUS))
+                }));
+                (first..targets.len()).collect()
+            })
+            .collect();
+    let ship_target = targets.len();
+    targets.push(Rect::from_center_size(map.ship.start, Vec2::ZERO));
+    let arena = Arena::new(map, targets);
+    let spawn_times: Vec<Vec<f32>> = teams
+        .iter()
+        .map(|&team| arena.travel_times(&arena.spawns(map, team)))
+        .collect();
+    let can_reach = |team: usize, ledges: &[usize]| {
+        ledges
+            .iter()
+            .any(|&ledge| spawn_times[team][ledge].is_finite())
+    };
+    let out_of_reach = |ledges: &[usize]| -> Vec<Team> {
+        (0..teams.len())
+            .filter(|&team| !can_reach(team, ledges))
+            .map(|team| teams[team])
+            .collect()
+    };
+    let mut problems = Vec::new();
+
+    for (bunch, &position) in map.berry_bunches.iter().enumerate() {
+        let teams = out_of_reach(&arena.ledges_touching(bunch));
+        if !teams.is_empty() {
+            problems.push(Problem::UnreachableBerries { position, teams });
+        }
+    }
+    for gate in &map.gates {
+        let area = Rect::from_center_size(gate.position, Vec2::new(GATE_WIDTH, GATE_HEIGHT));
+        let teams = out_of_reach(&arena.ledges_in(area));
+        if !teams.is_empty() {
+            problems.push(Problem::UnreachableGate {
+                position: gate.position,
+                teams,
+            });
+        }
+    }
+    let teams_without_ship = out_of_reach(&arena.ledges_touching(ship_target));
+    if !teams_without_ship.is_empty() {
+        problems.push(Problem::UnreachableShip {
+            teams: teams_without_ship,
+        });
+    }
+
+    // the ledges each team drops its berries off from
+    let home_ledges: Vec<Vec<usize>> = cell_targets
+        .iter()
+        .map(|cells| {
+            let ledges: Vec<Vec<usize>> = cells
+                .iter()
+                .map(|&cell| arena.ledges_touching(cell))
+                .collect();
+            if ledges.iter().all(|ledges| !ledges.is_empty()) {
+                ledges.concat()
+            } else {
+                Vec::new()
+            }
+        })
+        .collect();
+    for (index, &team) in teams.iter().enumerate() {
+        if !can_reach(index, &home_ledges[index]) {
+            problems.push(Problem::UnreachableCells { team });
+        }
+    }
+
+    let times_from: Vec<Vec<f32>> = (0..arena.ledges.len())
+        .map(|ledge| arena.travel_times(&[(ledge, 0.0)]))
+        .collect();
+    let time_home = |team: usize, from: usize| {
+        home_ledges[team]
+            .iter()
+            .map(|&home| times_from[from][home])
+            .fold(f32::INFINITY, f32::min)
+    };
+    for (index, ledge) in arena.ledges.iter().enumerate() {
+        if spawn_times.iter().all(|times| times[index].is_infinite()) {
+            problems.push(Problem::UnreachableLedge {
+                position: ledge.middle(),
+            });
+            continue;
+        }
+        // teams that cannot get home at all already have a problem
+        let stuck: Vec<Team> = (0..teams.len())
+            .filter(|&team| {
+                spawn_times[team][index].is_finite()
+                    && can_reach(team, &home_ledges[team])
+                    && time_home(team, index).is_infinite()
+            })
+            .map(|team| teams[team])
+            .collect();
+        if !stuck.is_empty() {
+            problems.push(Problem::SoftLock {
+                position: ledge.middle(),
+                teams: stuck,
+            });
+        }
+    }
+
+    // a mirrored map is fair, anything else has to give every team the same trip for berries
+    if !is_mirrored(map) {
+        let berry_runs: Vec<Option<f32>> = (0..teams.len())
+            .map(|team| {
+                let runs: Vec<f32> = (0..map.berry_bunches.len())
+                    .map(|bunch| {
+                        arena
+                            .ledges_touching(bunch)
+                            .into_iter()
+                            .map(|ledge| spawn_times[team][ledge] + time_home(team, ledge))
+                            .fold(f32::INFINITY, f32::min)
+                    })
+                    .filter(|time| time.is_finite())
+                    .collect();
+                (!runs.is_empty()).then(|| runs.iter().sum::<f32>() / runs.len() as f32)
+            })
+            .collect();
+        let quickest = (0..teams.len())
+            .filter_map(|team| berry_runs[team].map(|seconds| (team, seconds)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((quickest, quickest_seconds)) = quickest {
+            for (team, seconds) in berry_runs.iter().enumerate() {
+                match seconds {
+                    Some(seconds) if *seconds > quickest_seconds * (1.0 + FAIRNESS_TOLERANCE) => {
+                        problems.push(Problem::Unfair {
+                            team: teams[team],
+                            seconds: *seconds,
+                            quickest: teams[quickest],
+                            quickest_seconds,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Validates the map files given on the command line, or every map the game knows when there
+/// are none, and prints what is wrong with them. `--berries-to-win` sets how many berry cells
+/// are checked, otherwise it is the default of the settings. Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let (berries_to_win, paths) = match args {
+        [flag, value, paths @ ..] if flag == "--berries-to-win" => match value.parse() {
+            Ok(berries_to_win) => (berries_to_win, paths),
+            Err(_) => {
+                eprintln!("--berries-to-win takes a number, not {value}");
+                return 1;
+            }
+        },
+        paths => (GameSettings::default().berries_to_win, paths),
+    };
+    let maps: Vec<Result<Map, String>> = if paths.is_empty() {
+        MapLibrary::load().maps.into_iter().map(Ok).collect()
+    } else {
+        paths.iter().map(|path| read_map(path)).collect()
+    };
+    let mut failed = false;
+    for map in maps {
+        match map {
+            Ok(map) => {
+                let problems = validate(&map, berries_to_win);
+                if problems.is_empty() {
+                    println!("{}: ok", map.name);
+                } else {
+                    failed = true;
+                    println!("{}:", map.name);
+                    for problem in problems {
+                        println!("  {problem}");
+                    }
+                }
+            }
+            Err(err) => {
+                failed = true;
+                eprintln!("{err}");
+            }
+        }
+    }
+    i32::from(failed)
+}
+
+fn read_map(path: &str) -> Result<Map, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    let map: Map = ron::from_str(&contents).map_err(|err| format!("{path}: {err}"))?;
+    if map.teams.len() < TEAMS.len() {
+        return Err(format!(
+            "{path}: lays out {} teams but {} can play",
+            map.teams.len(),
+            TEAMS.len()
+        ));
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_maps_are_valid() {
+        for map in Map::built_in() {
+            let problems = validate(&map, GameSettings::default().berries_to_win);
+            assert!(problems.is_empty(), "{}: {problems:?}", map.name);
+        }
+    }
+
+    #[test]
+    fn berries_out_of_jumping_reach_are_found() {
+        // with only the floor left, workers cannot jump halfway up the screen
+        let mut map = Map::built_in().remove(0);
+        map.platforms.truncate(1);
+        let position = Vec2::new(0.0, WINDOW_BOTTOM_Y + WINDOW_HEIGHT / 2.0);
+        map.berry_bunches.push(position);
+        let problems = validate(&map, GameSettings::default().berries_to_win);
+        assert!(problems.iter().any(|problem| matches!(
+            problem,
+            Problem::UnreachableBerries { position: found, .. } if *found == position
+        )));
+    }
+}
@@ -0,0 +1,219 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use leafwing_input_manager::action_state::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{
+        Action, Direction, Killable, PlayerDeaths, PLAYER_COLLIDER_WIDTH_MULTIPLIER,
+        QUEEN_RENDER_WIDTH,
+    },
+    teams::Team,
+};
+
+const STINGER_SPEED: f32 = 900.0;
+
+pub struct WeaponsPlugin;
+
+impl Plugin for WeaponsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (use_weapons, weapon_hits, remove_hitboxes).chain());
+    }
+}
+
+/// What a fighter attacks with, queens only fight with their body
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weapon {
+    #[default]
+    Sword,
+    /// Reaches further, but takes longer to swing again
+    Lance,
+    /// Shoots a stinger a short way forward
+    Stinger,
+}
+
+impl Weapon {
+    pub const ALL: [Weapon; 3] = [Weapon::Sword, Weapon::Lance, Weapon::Stinger];
+
+    fn hitbox_size(&self) -> Vec2 {
+        match self {
+            Weapon::Sword => Vec2::new(40.0, 56.0),
+            Weapon::Lance => Vec2::new(110.0, 14.0),
+            Weapon::Stinger => Vec2::new(24.0, 14.0),
+        }
+    }
+
+    /// How long the hitbox stays out after an attack
+    fn duration(&self) -> f32 {
+        match self {
+            Weapon::Sword => 0.15,
+            Weapon::Lance => 0.25,
+            Weapon::Stinger => 0.3,
+        }
+    }
+
+    fn cooldown(&self) -> f32 {
+        match self {
+            Weapon::Sword => 0.4,
+            Weapon::Lance => 0.9,
+            Weapon::Stinger => 1.0,
+        }
+    }
+
+    /// How far in front of the fighter an attack can hit
+    pub fn reach(&self) -> f32 {
+        match self {
+            Weapon::Stinger => STINGER_SPEED * self.duration(),
+            _ => self.hitbox_size().x,
+        }
+    }
+
+    fn texture(&self) -> &'static str {
+        match self {
+            Weapon::Sword => "weapons/sword.png",
+            Weapon::Lance => "weapons/lance.png",
+            Weapon::Stinger => "weapons/stinger.png",
+        }
+    }
+}
+
+#[derive(Component)]
+struct WeaponCooldown(Timer);
+
+/// Kills players of other teams that it touches until its time runs out
+#[derive(Component)]
+struct Hitbox {
+    team: Team,
+    timer: Timer,
+    projectile: bool,
+    /// Projectiles are spent after their first hit and removed with the expired hitboxes
+    spent: bool,
+}
+
+fn use_weapons(
+    mut fighters: Query<(
+        Entity,
+        &ActionState<Action>,
+        &Weapon,
+        Option<&mut WeaponCooldown>,
+        &Direction,
+        &Team,
+        &Transform,
+    )>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+) {
+    for (entity, action_state, weapon, maybe_cooldown, direction, &team, transform) in &mut fighters
+    {
+        if let Some(mut cooldown) = maybe_cooldown {
+            cooldown.0.tick(time.delta());
+            if !cooldown.0.finished() {
+                continue;
+            }
+        }
+        if !action_state.just_pressed(&Action::Attack) {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .insert(WeaponCooldown(Timer::from_seconds(
+                weapon.cooldown(),
+                TimerMode::Once,
+            )));
+
+        let facing = match direction {
+            Direction::Right => 1.0,
+            Direction::Left => -1.0,
+        };
+        let size = weapon.hitbox_size();
+        let offset = Vec3::new(
+            facing * (QUEEN_RENDER_WIDTH * PLAYER_COLLIDER_WIDTH_MULTIPLIER + size.x) / 2.0,
+            0.0,
+            1.0,
+        );
+        let is_projectile = *weapon == Weapon::Stinger;
+        // swung weapons are children of the fighter so they move along with it
+        let translation = if is_projectile {
+            transform.translation + offset
+        } else {
+            offset
+        };
+        let hitbox = (
+            SpriteBundle {
+                texture: asset_server.load(weapon.texture()),
+                sprite: Sprite {
+                    custom_size: Some(size),
+                    color: team.color(),
+                    flip_x: facing < 0.0,
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(translation),
+                ..Default::default()
+            },
+            Hitbox {
+                team,
+                timer: Timer::from_seconds(weapon.duration(), TimerMode::Once),
+                projectile: is_projectile,
+                spent: false,
+            },
+            Collider::cuboid(size.x / 2.0, size.y / 2.0),
+            Sensor,
+            // hitboxes should not weigh the fighter down
+            ColliderMassProperties::Density(0.0),
+            ActiveEvents::COLLISION_EVENTS,
+            // riders on the ship are fixed bodies
+            ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        );
+        if is_projectile {
+            commands.spawn((
+                hitbox,
+                RigidBody::KinematicVelocityBased,
+                Velocity::linear(Vec2::new(facing * STINGER_SPEED, 0.0)),
+            ));
+        } else {
+            let hitbox = commands.spawn(hitbox).id();
+            commands.entity(entity).add_child(hitbox);
+        }
+    }
+}
+
+fn weapon_hits(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut hitboxes: Query<&mut Hitbox>,
+    players: Query<Killable>,
+    mut deaths: PlayerDeaths,
+) {
+    for collision_event in collision_events.read() {
+        if let CollisionEvent::Started(entity1, entity2, _) = collision_event {
+            for (hitbox_entity, player_entity) in [(entity1, entity2), (entity2, entity1)] {
+                let (Ok(mut hitbox), Ok(player)) = (
+                    hitboxes.get_mut(*hitbox_entity),
+                    players.get(*player_entity),
+                ) else {
+                    continue;
+                };
+                if hitbox.spent || *player.3 == hitbox.team || deaths.is_killed(*player_entity) {
+                    continue;
+                }
+                if hitbox.projectile {
+                    hitbox.spent = true;
+                }
+                deaths.kill(player);
+            }
+        }
+    }
+}
+
+fn remove_hitboxes(
+    mut hitboxes: Query<(Entity, &mut Hitbox)>,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    for (entity, mut hitbox) in &mut hitboxes {
+        hitbox.timer.tick(time.delta());
+        if hitbox.timer.finished() || hitbox.spent {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}